@@ -0,0 +1,147 @@
+use crate::{comp_util, connection, state};
+use common::MessageType;
+use yew::{html, Html};
+
+/// Modal for solving captchas issued by the server
+pub type Captcha = comp_util::HookedComponent<Inner>;
+
+#[derive(Default)]
+pub struct Inner {
+	/// Tag to select all the images of
+	tag: String,
+
+	/// Data URLs of the images to select from
+	images: Vec<String>,
+
+	/// Selection state of each image
+	selected: Vec<bool>,
+
+	/// Solution already sent for the current challenge
+	sent: bool,
+}
+
+pub enum Message {
+	CaptchaChange,
+	Toggle(usize),
+	Submit,
+}
+
+impl comp_util::Inner for Inner {
+	type Message = Message;
+	type Properties = ();
+
+	#[inline]
+	fn init(&mut self, c: &mut comp_util::Ctx<Self>) {
+		self.read_challenge(c);
+	}
+
+	#[inline]
+	fn update_message() -> Self::Message {
+		Message::CaptchaChange
+	}
+
+	#[inline]
+	fn subscribe_to(_: &Self::Properties) -> Vec<state::Change> {
+		vec![state::Change::Captcha]
+	}
+
+	fn update(
+		&mut self,
+		c: &mut comp_util::Ctx<Self>,
+		msg: Self::Message,
+	) -> bool {
+		use Message::*;
+
+		match msg {
+			CaptchaChange => {
+				self.read_challenge(c);
+				true
+			}
+			Toggle(i) => match self.selected.get_mut(i) {
+				Some(s) => {
+					*s = !*s;
+					true
+				}
+				None => false,
+			},
+			Submit => {
+				if self.sent {
+					return false;
+				}
+				self.sent = true;
+				connection::send(
+					MessageType::Captcha,
+					&self
+						.selected
+						.iter()
+						.enumerate()
+						.filter(|(_, s)| **s)
+						.map(|(i, _)| i as u8)
+						.collect::<Vec<u8>>(),
+				);
+				true
+			}
+		}
+	}
+
+	fn view(&self, c: &comp_util::Ctx<Self>) -> Html {
+		if self.images.is_empty() {
+			return html! {};
+		}
+
+		html! {
+			<div class="modal glass show">
+				<b>
+					{localize!("captcha_select", { "tag" => &self.tag })}
+				</b>
+				<div class="captcha-container">
+					{
+						for self.images.iter().enumerate().map(|(i, src)| {
+							html! {
+								<img
+									src=src.clone()
+									class=if self.selected[i] {
+										"selected"
+									} else {
+										""
+									}
+									onclick=c.link().callback(move |_| {
+										Message::Toggle(i)
+									})
+								/>
+							}
+						})
+					}
+				</div>
+				<input
+					type="button"
+					value=localize!("submit")
+					disabled=self.sent || !self.selected.contains(&true)
+					onclick=c.link().callback(|_| Message::Submit)
+				/>
+			</div>
+		}
+	}
+}
+
+impl Inner {
+	/// Read the current captcha challenge from the global state
+	fn read_challenge(&mut self, c: &comp_util::Ctx<Self>) {
+		*self = Default::default();
+		if let Some(ch) = &c.app_state().captcha {
+			self.tag = ch.tag.replace('_', " ");
+			self.images = ch
+				.images
+				.iter()
+				.map(|img| {
+					format!(
+						"data:{};base64,{}",
+						img.mime,
+						base64::encode(&img.data)
+					)
+				})
+				.collect();
+			self.selected = vec![false; self.images.len()];
+		}
+	}
+}
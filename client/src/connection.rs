@@ -13,11 +13,6 @@ use yew_services::timeout::{TimeoutService, TimeoutTask};
 
 // TODO: break up into submodules
 
-// TODO: send open post reclamation request with a full text body for any open
-// post on reconnect (via notification). Server-side it should be handled as
-// Client -> ThreadFeed -> Client -> websocket response (confirmation or
-// failure)
-
 /// Encode message and log it in debug mode
 pub fn encode_msg<T>(
 	enc: &mut Encoder,
@@ -75,6 +70,12 @@ pub enum State {
 	/// Server disconnected client with a critical error. This should mean a
 	/// programming error of some sort.
 	CriticalError,
+
+	/// Server disconnected client because of a ban
+	Banned,
+
+	/// Server is shutting down and will be reconnected to after a delay
+	Restarting,
 }
 
 impl Default for State {
@@ -104,6 +105,9 @@ pub struct Connection {
 	/// Reconnection timer
 	reconn_timer: Option<TimeoutTask>,
 
+	/// Seconds to wait before reconnecting, if the server announced a restart
+	restart_delay: Option<u32>,
+
 	/// Connection to server
 	socket: Option<web_sys::WebSocket>,
 
@@ -175,6 +179,7 @@ impl Agent for Connection {
 			state: State::Loading,
 			reconn_attempts: 0,
 			reconn_timer: None,
+			restart_delay: None,
 			socket: None,
 			handler_closures: Default::default(),
 			subscribers: HashSet::new(),
@@ -223,6 +228,17 @@ impl Agent for Connection {
 				}
 			}
 			Close(e) => {
+				if self.state == State::Banned {
+					// Do not attempt to reconnect till the page is reloaded
+					self.reset_socket_and_timer();
+					return;
+				}
+				if let Some(delay) = self.restart_delay.take() {
+					self.reset_socket_and_timer();
+					self.handle_restart(delay);
+					return;
+				}
+
 				let r = e.reason();
 				if e.code() != 1000 && !r.is_empty() {
 					if r == "unknown public key ID" {
@@ -242,7 +258,8 @@ impl Agent for Connection {
 				self.set_state(State::Disconnected);
 			}
 			TryReconnecting => {
-				if self.state == State::Disconnected {
+				if matches!(self.state, State::Disconnected | State::Restarting)
+				{
 					self.connect();
 				}
 			}
@@ -340,9 +357,28 @@ impl Connection {
 			self.link.callback(|_| Event::TryReconnecting),
 		));
 
+		// Captchas are bound to the connection they were issued on
+		state::Agent::dispatcher().send(state::Request::SetCaptcha(None));
+		crate::pow::cancel();
+
 		self.set_state(State::Disconnected);
 	}
 
+	/// Reconnect only after the delay requested by a restarting server
+	#[cold]
+	fn handle_restart(&mut self, delay: u32) {
+		self.reconn_timer = Some(TimeoutService::spawn(
+			std::time::Duration::from_secs(delay as u64),
+			self.link.callback(|_| Event::TryReconnecting),
+		));
+
+		// Captchas are bound to the connection they were issued on
+		state::Agent::dispatcher().send(state::Request::SetCaptcha(None));
+		crate::pow::cancel();
+
+		self.set_state(State::Restarting);
+	}
+
 	#[cold]
 	fn close_socket(&mut self) {
 		if let Some(s) = &self.socket {
@@ -559,7 +595,8 @@ impl Connection {
 					}
 
 					match req.status {
-						PubKeyStatus::Accepted => {
+						PubKeyStatus::Accepted { staff_level } => {
+							send(Request::SetStaffLevel(staff_level));
 							util::with_logging(|| {
 								self.set_state(State::HandshakeComplete);
 								for msg in std::mem::take(&mut self.deferred) {
@@ -568,18 +605,18 @@ impl Connection {
 								Ok(())
 							});
 						}
-						PubKeyStatus::NeedResend => {
+						PubKeyStatus::Challenge { nonce } => {
 							// Key already saved in database. Need to confirm
-							// it's the same private key by sending a
-							// HandshakeReq with Authentication::Saved.
+							// it's the same private key by signing the nonce
+							// issued by the server.
 							let mut kp = self.app_state.get().key_pair.clone();
 							kp.id = req.id.into();
-							Self::send_handshake_req(kp);
+							Self::send_challenge_res(kp, req.id, nonce);
 						}
 						PubKeyStatus::NotFound => {
 							send(Request::SetKeyID(None));
 							let mut kp = self.app_state.get().key_pair.clone();
-							kp.id = req.id.into();
+							kp.id = None;
 							Self::send_handshake_req(kp);
 						}
 					};
@@ -604,6 +641,7 @@ impl Connection {
 				}
 				PatchPostBody => send(Request::PatchPostBody(decode!())),
 				ClosePost => send(Request::ClosePost(decode!())),
+				Post => send(Request::RegisterPost(decode!())),
 				PartitionedPageStart => {
 					skip_payload!();
 					let mut posts = Vec::<common::payloads::Post>::new();
@@ -661,6 +699,77 @@ impl Connection {
 					));
 				}
 				Configs => send(Request::SetConfigs(decode!())),
+				ReclaimOpenPost => {
+					use crate::post::posting;
+
+					posting::Agent::dispatcher()
+						.send(posting::Request::SetReclaimResult(decode!()));
+				}
+				NeedCaptcha => {
+					use crate::post::posting;
+
+					skip_payload!();
+					posting::Agent::dispatcher()
+						.send(posting::Request::NeedCaptcha);
+				}
+				Captcha => {
+					use crate::post::posting;
+					use common::payloads::CaptchaRes;
+
+					match decode!() {
+						CaptchaRes::Challenge(c) => {
+							send(Request::SetCaptcha(c.into()))
+						}
+						CaptchaRes::Solved => {
+							send(Request::SetCaptcha(None));
+							posting::Agent::dispatcher()
+								.send(posting::Request::CaptchaSolved);
+						}
+						CaptchaRes::Invalid => {
+							util::alert(&localize!("invalid_captcha"))
+						}
+						CaptchaRes::Expired => posting::Agent::dispatcher()
+							.send(posting::Request::CaptchaExpired),
+					}
+				}
+				ProofOfWork => crate::pow::solve(decode!()),
+				Moderate => send(Request::ModeratePost(decode!())),
+				Banned => {
+					let ban: common::payloads::moderation::BanNotice =
+						decode!();
+					let expires: String = js_sys::Date::new(
+						&(ban.expires as f64 * 1000.0).into(),
+					)
+					.to_locale_string("default", &Default::default())
+					.into();
+					self.set_state(State::Banned);
+					util::alert(&localize!(
+						"banned_until",
+						{
+							"expires" => expires.as_str()
+							"reason" => ban.reason.as_str()
+						}
+					));
+				}
+				PrunePosts => send(Request::PrunePosts(decode!())),
+				DeleteThread => send(Request::RemoveThread(decode!())),
+				ServerRestarting => {
+					self.restart_delay = Some(decode!());
+					self.set_state(State::Restarting);
+				}
+				Search => {
+					use common::payloads::SearchReply;
+
+					match decode!() {
+						SearchReply::Results(res) => {
+							send(Request::SetSearchResults(res))
+						}
+						SearchReply::RateLimited => {
+							util::alert(&localize!("search_rate_limited"))
+						}
+					}
+				}
+				SynchronizeToPost => send(Request::SetPostLocation(decode!())),
 				_ => error!("unhandled message type: {:?}", t),
 			}
 		}
@@ -668,60 +777,60 @@ impl Connection {
 		Ok(())
 	}
 
-	/// Asynchronously generate and send a handshake request message
+	/// Send a handshake request message
 	#[cold]
 	fn send_handshake_req(key_pair: KeyPair) {
 		use common::payloads::Authorization;
 
+		util::with_logging(|| {
+			let auth = match &key_pair.id {
+				Some(id) => Authorization::Saved(id.clone()),
+				None => Authorization::NewPubKey(key_pair.public.clone()),
+			};
+			Self::dispatch_handshake_req(key_pair, auth)
+		});
+	}
+
+	/// Asynchronously sign a handshake challenge issued by the server and send
+	/// the response
+	#[cold]
+	fn send_challenge_res(key_pair: KeyPair, id: uuid::Uuid, nonce: [u8; 32]) {
+		use common::payloads::Authorization;
+
 		wasm_bindgen_futures::spawn_local(util::with_logging_async(
 			async move {
-				let crypto = util::window().crypto()?;
-				let mut enc = common::Encoder::new(Vec::new());
-				encode_msg(
-					&mut enc,
-					MessageType::Handshake,
-					&common::payloads::HandshakeReq {
-						protocol_version: common::VERSION,
-						auth: match &key_pair.id {
-							Some(id) => {
-								let mut nonce: [u8; 32] = unsafe {
-									std::mem::MaybeUninit::uninit()
-										.assume_init()
-								};
-								crypto.get_random_values_with_u8_array(
-									&mut nonce,
-								)?;
-
-								Authorization::Saved {
-									id: id.clone(),
-									nonce,
-									signature: key_pair
-										.sign(&mut {
-											let mut buf =
-												Vec::with_capacity(16 + 32);
-											buf.extend(id.as_bytes());
-											buf.extend(&nonce);
-											buf
-										})
-										.await?,
-								}
-							}
-							None => Authorization::NewPubKey(
-								key_pair.public.clone(),
-							),
-						},
-					},
-				)?;
-
-				Connection::dispatcher().send(Request::Handshake {
+				let signature = key_pair.sign_challenge(&id, &nonce).await?;
+				Self::dispatch_handshake_req(
 					key_pair,
-					message: enc.finish()?,
-				});
-
-				Ok(())
+					Authorization::SignedChallenge { id, signature },
+				)
 			},
 		));
 	}
+
+	/// Encode a handshake request and pass it to the connection agent for
+	/// sending
+	fn dispatch_handshake_req(
+		key_pair: KeyPair,
+		auth: common::payloads::Authorization,
+	) -> util::Result {
+		let mut enc = common::Encoder::new(Vec::new());
+		encode_msg(
+			&mut enc,
+			MessageType::Handshake,
+			&common::payloads::HandshakeReq {
+				protocol_version: common::VERSION,
+				auth,
+			},
+		)?;
+
+		Connection::dispatcher().send(Request::Handshake {
+			key_pair,
+			message: enc.finish()?,
+		});
+
+		Ok(())
+	}
 }
 
 pub struct SyncCounter {
@@ -753,7 +862,7 @@ impl Component for SyncCounter {
 		use State::*;
 
 		let mut cls = vec!["banner-float"];
-		if self.current == State::CriticalError {
+		if matches!(self.current, CriticalError | Banned) {
 			cls.push("admin");
 		}
 
@@ -767,6 +876,8 @@ impl Component for SyncCounter {
 							HandshakeComplete => "connected",
 							Disconnected => "disconnected",
 							CriticalError => "critical_error",
+							Banned => "banned",
+							Restarting => "restarting",
 						}
 					}
 				}
@@ -10,10 +10,12 @@ mod util;
 #[macro_use]
 mod comp_util;
 mod buttons;
+mod captcha;
 mod connection;
 mod mouse;
 mod page_selector;
 mod post;
+mod pow;
 mod state;
 mod thread;
 mod thread_index;
@@ -87,7 +89,8 @@ impl Component for App {
 						/>
 					</div>
 					<div class="overlay" id="modal-overlay">
-						// TODO: modals
+						// TODO: other modals
+						<captcha::Captcha />
 					</div>
 					<div class="overlay" id="hover-overlay">
 						// TODO: hover previews (post and image)
@@ -119,6 +122,17 @@ pub async fn main_js() -> util::Result {
 	err1?;
 	err2?;
 
+	// Server-side rendered content is not hydrated. The pinned Yew revision
+	// has no hydration support, so the content is removed and the
+	// application renders the page from scratch. Hydration requires
+	// upgrading to a Yew release supporting it and rendering the same markup
+	// on the server with it.
+	//
+	// The content is only removed once the application is ready to render,
+	// so the page is never left blank while loading.
+	if let Some(el) = util::document().get_element_by_id("ssr") {
+		el.remove();
+	}
 	yew::start_app::<App>();
 
 	Ok(())
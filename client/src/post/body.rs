@@ -1,7 +1,6 @@
 use super::common::{Ctx, Message, PostComponent};
 use crate::state;
 use common::payloads::post_body::{Command, Node, PendingNode};
-use std::fmt::Write;
 use wasm_bindgen::JsCast;
 use web_sys::{EventTarget, MouseEvent};
 use yew::{html, Html};
@@ -87,14 +86,16 @@ fn render_post_link<'c, PC>(
 where
 	PC: PostComponent + 'static,
 {
-	// TODO: Attempt to look up link thread and page in global collection,
-	// if not passed from server
-	// TODO: Persist all single post fetches from server in global post
-	// collection
+	// If thread = 0, link has not had it's parenthood looked up yet on the
+	// server. Attempt to look it up in the global collection.
+	let cached = c.app_state().posts.get(&id).map(|p| (p.thread, p.page));
+	let (thread, page) = match cached {
+		Some(loc) if thread == 0 => loc,
+		_ => (thread, page),
+	};
+
 	let mut text = format!(">>{}", id);
 	if match &c.app_state().location.feed {
-		// If thread = 0, link has not had it's parenthood looked up yet on the
-		// server
 		state::FeedID::Thread { id: feed_id, .. } => {
 			thread != 0 && feed_id != &thread
 		}
@@ -111,18 +112,34 @@ where
 		// TODO: Hover preview on both
 		<>
 			// TODO: inline post on click
-			<a>{text}</a>
+			<a
+				onmouseenter=c.link().callback(move |_| {
+					// Fetch posts from other threads and pages not loaded yet
+					// for the preview
+					if cached.is_none() {
+						state::fetch_post(id);
+					}
+					Message::NOP
+				})
+			>
+				{text}
+			</a>
 			<a
 				onclick=c.link().callback(move |_| {
 					// TODO:  Handle middle click
 
-					state::navigate_to(state::Location{
-						feed: state::FeedID::Thread{
-							id: thread,
-							page: page as i32,
-						},
-						focus: Some(state::Focus::Post(id)),
-					});
+					if thread == 0 {
+						// Location unknown. Resolve it on the server.
+						state::navigate_to_post(id);
+					} else {
+						state::navigate_to(state::Location{
+							feed: state::FeedID::Thread{
+								id: thread,
+								page: page as i32,
+							},
+							focus: Some(state::Focus::Post(id)),
+						});
+					}
 					Message::NOP
 				})
 			>
@@ -133,105 +150,13 @@ where
 }
 
 fn render_command(comm: &Command) -> Html {
-	use Command::*;
-
-	let mut class = "";
-	let inner = match comm {
-		Countdown { start, secs } => {
-			return html! {
-				<super::countdown::Countdown time=start+secs />
-			}
-		}
-		Autobahn(hours) => format!("#autobahn({})", hours),
-		EightBall(msg) => format!("#8ball ({})", msg),
-		Flip(b) => format!("#flip ({})", if *b { "flap" } else { "flop" }),
-		Pyu(n) => format!("#pyu ({})", n),
-		PCount(n) => format!("#pcount ({})", n),
-		Dice {
-			offset,
-			faces,
-			results,
-		} => {
-			let mut s = String::from("#");
-			let sign = if offset < &0 { '-' } else { '+' };
-
-			macro_rules! push {
-				($format:expr, $($arg:expr),+) => {
-					write!(&mut s, $format, $($arg),+).unwrap();
-				};
-			}
-
-			if results.len() > 1 {
-				push!("{}", results.len());
-			}
-			push!("d{}", faces);
-			if offset != &0 {
-				push!("{}{}", sign, offset.abs());
-			}
-			s += " (";
-
-			let mut sum = 0_i32;
-			for (i, r) in results.iter().enumerate() {
-				if i != 0 {
-					s += " + ";
-				}
-				sum += *r as i32;
-				push!("{}", r);
-			}
-			if offset != &0 {
-				sum += *offset as i32;
-				push!(" {} {}", sign, offset.abs());
-			}
-			if results.len() != 1 || offset != &0 {
-				push!(" = {}", sum);
-			}
-			s.push(')');
-
-			let max_raw = *faces as i32 * results.len() as i32;
-			let max = max_raw + *offset as i32;
-			// No special formatting for small rolls
-			if max_raw >= 10 && faces != &1 {
-				if sum == max {
-					class = "max-roll";
-				} else if sum as usize == results.len() {
-					class = "shit-roll";
-				} else if sum == 69 || sum == 6969 {
-					class = "lewd-roll";
-				} else if {
-					let mut n = sum;
-					let digit = sum % 10;
-					let mut repeating = false;
-					loop {
-						n /= 10;
-						if n == 0 {
-							repeating = true;
-							break;
-						}
-						if n % 10 != digit {
-							break;
-						}
-					}
-					repeating
-				} {
-					match sum {
-						11..=99 => {
-							class = "dubs-roll";
-						}
-						111..=999 => {
-							class = "trips-roll";
-						}
-						1111..=9999 => {
-							class = "quads-roll";
-						}
-						_ => (),
-					}
-				}
-			}
-
-			s
-		}
-	};
+	if let Command::Countdown { start, secs } = comm {
+		return html! {
+			<super::countdown::Countdown time=start+secs />
+		};
+	}
 
+	let (inner, class) = comm.format();
 	html! {
 		<strong class=class>{inner}</strong>
 	}
@@ -241,47 +166,13 @@ fn render_pending_node<'c, PC>(c: &Ctx<'c, PC>, n: &PendingNode) -> Html
 where
 	PC: PostComponent + 'static,
 {
-	use PendingNode::*;
-
-	let inner = match n {
-		Flip => "#flip (?)".into(),
-		EightBall => "#8ball (?)".into(),
-		Pyu => "#pyu (?)".into(),
-		PCount => "#pcount (?)".into(),
-		Countdown(n) => format!("#countdown({})", n),
-		Autobahn(n) => format!("#autobahn({})", n),
-		PostLink(id) => match c.app_state().posts.get(id) {
-			Some(p) => return render_post_link(c, *id, p.thread, p.page),
-			None => format!(">>{}", id),
-		},
-		Dice {
-			offset,
-			faces,
-			rolls,
-		} => {
-			let mut s = String::from("#");
-			let sign = if offset < &0 { '-' } else { '+' };
-
-			macro_rules! push {
-				($format:expr, $($arg:expr),+) => {
-					write!(&mut s, $format, $($arg),+).unwrap();
-				};
-			}
-
-			if rolls > &1 {
-				push!("{}", rolls);
-			}
-			push!("d{}", faces);
-			if offset != &0 {
-				push!(" {}{}", sign, offset.abs());
-			}
-			s += " (?)";
-
-			s
+	if let PendingNode::PostLink(id) = n {
+		if let Some(p) = c.app_state().posts.get(id) {
+			return render_post_link(c, *id, p.thread, p.page);
 		}
-	};
+	}
 
 	html! {
-		<strong class="pending">{inner}</strong>
+		<strong class="pending">{n.format()}</strong>
 	}
 }
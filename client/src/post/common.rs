@@ -486,12 +486,21 @@ where
 		if !self.inner.should_render(&c) {
 			return html! {};
 		}
+		if p.shadow_binned {
+			let s = c.app_state();
+			if s.staff_level.is_none() && !s.mine.contains(&p.id) {
+				return html! {};
+			}
+		}
 
 		let mut cls = vec!["glass"];
 		cls.extend(self.inner.extra_classes(&c));
 		if p.open {
 			cls.push("open");
 		}
+		if p.deleted {
+			cls.push("deleted");
+		}
 		match &c.app_state().location.focus {
 			Some(Focus::Post(id)) if id == &c.post().id => {
 				cls.push("highlight");
@@ -535,7 +544,7 @@ where
 					{with_image!(render_figure)}
 					<blockquote>{self.inner.render_body(&c)}</blockquote>
 				</div>
-				// TODO: post moderation log
+				{self.render_moderation_log(&c)}
 				// TODO: backlinks
 				{self.inner.render_after(&c)}
 			</article>
@@ -711,6 +720,63 @@ where
 		}
 	}
 
+	fn render_moderation_log<'c>(&self, c: &Ctx<'c, PC>) -> Html {
+		use common::payloads::moderation::{
+			ModerationAction::*, ModerationLevel::*,
+		};
+
+		let log = &c.post().moderation_log;
+		if log.is_empty() {
+			return html! {};
+		}
+
+		html! {
+			<div class="moderation-log">
+				{
+					for log.iter().map(|e| {
+						let staff = match (&e.staff_title, e.staff_level) {
+							(Some(title), _) => title.as_str(),
+							(None, Some(Janitor)) => localize!("janitors"),
+							(None, Some(Moderator)) => localize!("moderators"),
+							(None, Some(Admin)) => localize!("admin"),
+							(None, None) => localize!("post_author"),
+						};
+						let action = localize!(match e.action {
+							DeletePost => "post_deleted",
+							DeleteImage => "image_deleted",
+							SpoilerImage => "image_spoilered",
+							PurgePost => "post_purged",
+							ShadowBinPost => "post_shadow_binned",
+							LockThread(true) => "thread_locked",
+							LockThread(false) => "thread_unlocked",
+							StickyThread(true) => "thread_stickied",
+							StickyThread(false) => "thread_unstickied",
+							CyclicThread(true) => "thread_made_cyclic",
+							CyclicThread(false) => "thread_made_acyclic",
+							BanPoster => "poster_banned",
+						});
+
+						let mut text = localize!(
+							"moderated_by",
+							{
+								"action" => action
+								"staff" => staff
+							}
+						);
+						if !e.reason.is_empty() {
+							text += ": ";
+							text += &e.reason;
+						}
+
+						html! {
+							<b class="admin">{text}</b>
+						}
+					})
+				}
+			</div>
+		}
+	}
+
 	fn render_name<'c>(&self, c: &Ctx<'c, PC>) -> Html {
 		// TODO: Staff titles
 		let mut w: Vec<Html> = Default::default();
@@ -1,4 +1,8 @@
-use crate::{comp_util, state::Change};
+use crate::{comp_util, connection, state::Change, util};
+use common::{
+	payloads::moderation::{ModerationAction, PostModeration},
+	MessageType,
+};
 use yew::{html, Html};
 
 #[derive(Default)]
@@ -11,6 +15,7 @@ pub type Menu = comp_util::HookedComponent<Inner>;
 pub enum Message {
 	Rerender,
 	ToggleExpand,
+	Moderate(ModerationAction),
 }
 
 impl comp_util::Inner for Inner {
@@ -24,13 +29,13 @@ impl comp_util::Inner for Inner {
 
 	#[inline]
 	fn subscribe_to(props: &Self::Properties) -> Vec<Change> {
-		vec![Change::Post(props.id)]
+		vec![Change::Post(props.id), Change::StaffLevel]
 	}
 
 	#[inline]
 	fn update(
 		&mut self,
-		_: &mut comp_util::Ctx<Self>,
+		c: &mut comp_util::Ctx<Self>,
 		msg: Self::Message,
 	) -> bool {
 		match msg {
@@ -39,6 +44,35 @@ impl comp_util::Inner for Inner {
 				self.expanded = !self.expanded;
 				true
 			}
+			Message::Moderate(action) => {
+				self.expanded = false;
+				let length = match action {
+					ModerationAction::BanPoster => {
+						match util::prompt(&localize!("ban_duration"))
+							.map(|s| s.trim().parse::<u32>().ok())
+							.flatten()
+						{
+							Some(hours) if hours != 0 => {
+								hours.saturating_mul(60 * 60)
+							}
+							_ => return true,
+						}
+					}
+					_ => 0,
+				};
+				if let Some(reason) = util::prompt(&localize!("reason")) {
+					connection::send(
+						MessageType::Moderate,
+						&PostModeration {
+							id: c.props().id,
+							action,
+							length,
+							reason,
+						},
+					);
+				}
+				true
+			}
 		}
 	}
 
@@ -63,7 +97,7 @@ impl comp_util::Inner for Inner {
 					if self.expanded {
 						html! {
 							<ul class="popup-menu glass no-select">
-								<li>{"TODO"}</li>
+								{self.render_moderation(c)}
 							</ul>
 						}
 					} else {
@@ -74,3 +108,42 @@ impl comp_util::Inner for Inner {
 		}
 	}
 }
+
+impl Inner {
+	/// Render moderation actions available to the user's staff level
+	fn render_moderation(&self, c: &comp_util::Ctx<Self>) -> Html {
+		use ModerationAction::*;
+
+		let s = c.app_state();
+		let level = match s.staff_level {
+			Some(l) => l,
+			None => return html! {},
+		};
+		let id = c.props().id;
+		let mut actions = vec![
+			(DeletePost, "delete_post"),
+			(DeleteImage, "delete_image"),
+			(SpoilerImage, "spoiler_image"),
+			(PurgePost, "purge_post"),
+			(ShadowBinPost, "shadow_bin"),
+			(BanPoster, "ban"),
+		];
+		if let Some(t) = s.threads.get(&id) {
+			actions.push((LockThread(!t.locked), "lock_thread"));
+			actions.push((StickyThread(!t.sticky), "sticky_thread"));
+			actions.push((CyclicThread(!t.cyclic), "cyclic_thread"));
+		}
+
+		actions
+			.into_iter()
+			.filter(|(a, _)| a.required_level() <= level)
+			.map(|(a, label)| {
+				html! {
+					<li onclick=c.link().callback(move |_| Message::Moderate(a))>
+						{localize!(label)}
+					</li>
+				}
+			})
+			.collect()
+	}
+}
@@ -4,7 +4,7 @@ use common::MessageType;
 use std::collections::HashMap;
 use wasm_bindgen::JsCast;
 use yew::{
-	agent::{AgentLink, Bridge, Bridged, HandlerId},
+	agent::{AgentLink, Bridge, Bridged, Dispatched, HandlerId},
 	html, ComponentLink, Html, NodeRef,
 };
 use yew_services::render::{RenderService, RenderTask};
@@ -81,6 +81,7 @@ impl PostComponent for Inner {
 		let mut a = Agent::bridge(link.callback(|msg| match msg {
 			State(s) => Extra(FormMessage::SetState(s)),
 			RenderQuoted(req) => Extra(FormMessage::QuotePost(req)),
+			CaptchaSolved => super::common::Message::NOP,
 		}));
 		a.send(Request::SubViewUpdates);
 		self.agent = a.into();
@@ -94,6 +95,7 @@ impl PostComponent for Inner {
 			Draft { .. }
 			| Allocated { .. }
 			| Allocating { .. }
+			| Reclaiming { .. }
 			| NeedCaptcha { .. }
 			| CriticalError => true,
 			Ready => false,
@@ -322,12 +324,15 @@ impl PostComponent for Inner {
 					/>
 				});
 			}
-			Allocated { .. } | Allocating { .. } => {
+			Allocated { .. } | Allocating { .. } | Reclaiming { .. } => {
 				inner.push(html! {
 					<input
 						type="button"
 						value=localize!("done")
-						disabled=matches!(self.state,  Allocating { .. })
+						disabled=matches!(
+							self.state,
+							Allocating { .. } | Reclaiming { .. }
+						)
 						onclick=c.link().callback(|_| {
 							Extra(FormMessage::Close)
 						})
@@ -440,10 +445,13 @@ pub enum State {
 	/// Post open and allocated to the server
 	Allocated { post: u64 },
 
+	/// Connection to the server was lost with the post still open. Sent a
+	/// request to reclaim the post on the new connection.
+	Reclaiming { post: u64 },
+
 	/// Captcha solution required to allocate to target thread.
 	/// This can only take place as an interrupt from the server during
 	/// Allocating.
-	#[allow(unused)] // TODO: remove it
 	NeedCaptcha { thread: u64 },
 
 	/// Client suffered unrecoverable error
@@ -493,6 +501,19 @@ pub enum Request {
 
 	/// Open a draft postform for a target thread
 	OpenDraft(u64),
+
+	/// Result of an open post reclamation request received from the server
+	SetReclaimResult(common::payloads::ReclaimOpenPostRes),
+
+	/// Server requested a captcha solution before allocating the post
+	NeedCaptcha,
+
+	/// Captcha solved. Any interrupted post allocation can be retried.
+	CaptchaSolved,
+
+	/// Submitted proof of work solution expired. Any interrupted post
+	/// allocation must be retried to receive a new challenge.
+	CaptchaExpired,
 }
 
 enum Subscription {
@@ -507,6 +528,10 @@ pub enum Response {
 
 	/// Render quoted post in view
 	RenderQuoted(PostQuoteReq),
+
+	/// Captcha or proof of work solved. Any request interrupted by the server
+	/// requiring a captcha can be retried.
+	CaptchaSolved,
 }
 
 /// Only one PostForm can exist at a time so this agent manages it
@@ -527,6 +552,9 @@ pub struct Agent {
 
 	/// Current state of the open post body text
 	post_body: Vec<char>,
+
+	/// Body sent to the server with the last open post reclamation request
+	reclaimed_body: Vec<char>,
 }
 
 impl yew::agent::Agent for Agent {
@@ -544,9 +572,7 @@ impl yew::agent::Agent for Agent {
 			link.callback(|_: web_sys::Event| Message::SelectionChange),
 		);
 
-		// TODO: locked thread handling
 		// TODO: don't reset on thread change. Just keep floating.
-		// TODO: claim existing open post on reconnection
 		// TODO: use onbeforeunload to prevent accidental closing of postform
 		// TODO: image insertion must specify target post
 		Self {
@@ -564,6 +590,7 @@ impl yew::agent::Agent for Agent {
 			subscribers: Default::default(),
 			last_selection: Default::default(),
 			post_body: Default::default(),
+			reclaimed_body: Default::default(),
 		}
 	}
 
@@ -599,15 +626,17 @@ impl yew::agent::Agent for Agent {
 						self.set_state(S::Draft { thread })
 					}
 
-					S::Allocated { post } => match cs {
-						// TODO: resend body and try to resend any missing
-						// buffered image, if a disconnect happened
-						CS::HandshakeComplete => {}
+					S::Allocated { post } | S::Reclaiming { post } => {
+						match cs {
+							// TODO: try to resend any missing buffered image,
+							// if a disconnect happened
+							CS::HandshakeComplete => self.reclaim(post),
 
-						// Message filtering and deferral on connection loss
-						// is handled by the connection module itself.
-						_ => (),
-					},
+							// Message filtering and deferral on connection
+							// loss is handled by the connection module itself.
+							_ => (),
+						}
+					}
 
 					// Already stalled on a critical error
 					S::CriticalError => (),
@@ -694,10 +723,53 @@ impl yew::agent::Agent for Agent {
 			}
 			CommitText(new) => self.commit_text(new.chars().collect()),
 			OpenDraft(thread) => {
-				if self.state == State::Ready {
+				if self.state == State::Ready && !self.is_locked(thread) {
 					self.set_state(State::Draft { thread });
 				}
 			}
+			NeedCaptcha => {
+				if let State::Allocating { thread } = self.state {
+					self.set_state(State::NeedCaptcha { thread });
+				}
+			}
+			CaptchaSolved => {
+				if let State::NeedCaptcha { thread } = self.state {
+					self.set_state(State::Draft { thread });
+					self.try_alloc();
+				}
+				for id in self.subscribers.keys() {
+					self.link.respond(*id, Response::CaptchaSolved);
+				}
+			}
+			CaptchaExpired => {
+				if let State::NeedCaptcha { thread } = self.state {
+					self.set_state(State::Draft { thread });
+					self.try_alloc();
+				}
+			}
+			SetReclaimResult(res) => {
+				use common::payloads::ReclaimOpenPostRes::*;
+
+				if let State::Reclaiming { post } = self.state {
+					match res {
+						Reclaimed => {
+							// Commit any changes made during reclamation
+							// against the body the server now has
+							let latest = std::mem::replace(
+								&mut self.post_body,
+								std::mem::take(&mut self.reclaimed_body),
+							);
+							self.set_state(State::Allocated { post });
+							self.commit_text(latest);
+						}
+						AlreadyClosed | NotFound | InUse => {
+							state::Agent::dispatcher()
+								.send(state::Request::SetOpenPostID(None));
+							self.reset();
+						}
+					}
+				}
+			}
 			Close => {
 				use State::*;
 
@@ -721,8 +793,12 @@ impl Agent {
 	fn set_state(&mut self, new: State) {
 		if self.state != new {
 			log::debug!("set postform state: {:?} -> {:?}", self.state, new);
-			self.state = new;
-			if matches!(new, State::Allocated { .. }) {
+			let old = std::mem::replace(&mut self.state, new);
+
+			// A reclaimed post already has its body on the server
+			if matches!(new, State::Allocated { .. })
+				&& !matches!(old, State::Reclaiming { .. })
+			{
 				self.commit_pending();
 			}
 			for id in self.subscribers.keys() {
@@ -753,6 +829,16 @@ impl Agent {
 		}
 	}
 
+	/// Return, if the thread is known to be locked
+	fn is_locked(&self, thread: u64) -> bool {
+		self.app_state
+			.get()
+			.threads
+			.get(&thread)
+			.map(|t| t.locked)
+			.unwrap_or(false)
+	}
+
 	/// Try allocating a post, if it is eligible and not yet allocated.
 	/// Returns, the agent state.
 	fn try_alloc(&mut self) -> &State {
@@ -760,6 +846,12 @@ impl Agent {
 
 		match self.state {
 			Draft { thread } => {
+				if self.is_locked(thread) {
+					util::alert(&localize!("thread_is_locked"));
+					self.reset();
+					return &self.state;
+				}
+
 				let s = self.app_state.get();
 				if !s.location.is_thread() {
 					return &self.state;
@@ -877,6 +969,20 @@ impl Agent {
 		self.post_body = new;
 	}
 
+	/// Request to reclaim an open post lost to a disconnection, sending the
+	/// full text body to the server
+	fn reclaim(&mut self, post: u64) {
+		self.reclaimed_body = self.post_body.clone();
+		connection::send(
+			MessageType::ReclaimOpenPost,
+			&common::payloads::ReclaimOpenPostReq {
+				id: post,
+				body: self.reclaimed_body.iter().collect(),
+			},
+		);
+		self.set_state(State::Reclaiming { post });
+	}
+
 	/// Commit any pending text or images
 	fn commit_pending(&mut self) {
 		if !self.post_body.is_empty() {
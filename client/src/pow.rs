@@ -0,0 +1,46 @@
+use crate::{connection, util};
+use common::{payloads::ProofOfWorkChallenge, MessageType};
+use std::cell::Cell;
+
+/// Number of nonces to try before yielding to the browser event loop
+const BATCH_SIZE: u64 = 1 << 14;
+
+thread_local! {
+	/// Incremented on each new challenge or cancellation to stop any solver
+	/// working on a superseded challenge
+	static GENERATION: Cell<u64> = Default::default();
+}
+
+/// Increment the solver generation and return the new value
+fn next_generation() -> u64 {
+	GENERATION.with(|g| {
+		g.set(g.get() + 1);
+		g.get()
+	})
+}
+
+/// Solve a proof of work challenge in the background and send the solution to
+/// the server
+pub fn solve(challenge: ProofOfWorkChallenge) {
+	let generation = next_generation();
+
+	wasm_bindgen_futures::spawn_local(util::with_logging_async(async move {
+		let mut start = 0;
+		loop {
+			if GENERATION.with(|g| g.get()) != generation {
+				return Ok(());
+			}
+			if let Some(nonce) = challenge.solve_range(start, BATCH_SIZE) {
+				connection::send(MessageType::ProofOfWork, &nonce);
+				return Ok(());
+			}
+			start += BATCH_SIZE;
+			util::yield_now().await?;
+		}
+	}));
+}
+
+/// Stop solving any current challenge
+pub fn cancel() {
+	next_generation();
+}
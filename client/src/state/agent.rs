@@ -1,12 +1,15 @@
 use super::{state, FeedID, Focus, Location, State};
 use crate::{connection::send, util};
 use common::{
-	payloads::{Post, Thread, ThreadWithPosts},
+	payloads::{moderation::ModerationAction, Post, Thread, ThreadWithPosts},
 	util::DoubleSetMap,
 	MessageType,
 };
 use indexmap::IndexSet;
-use std::collections::{hash_map::Entry, HashMap};
+use std::{
+	collections::{hash_map::Entry, HashMap, HashSet},
+	rc::Rc,
+};
 use wasm_bindgen::JsCast;
 use yew::{
 	agent::{AgentLink, Bridge, Context, Dispatched, HandlerId},
@@ -38,6 +41,10 @@ const SCROLL_TO_FOCUSED: u8 = 1 << 2;
 /// Do not trigger updates on new location setting
 const NO_TRIGGER: u8 = 1 << 3;
 
+/// The server already synchronizes the client to the feed and sends the
+/// requested page, so no requests need to be sent
+const SERVER_SYNCED: u8 = 1 << 4;
+
 /// Subscribe to updates of a value type
 pub enum Request {
 	NotifyChange(Vec<Change>),
@@ -57,6 +64,16 @@ pub enum Request {
 		flags: u8,
 	},
 
+	/// Navigate to a post with an unknown location
+	NavigateToPost(u64),
+
+	/// Set the location of a post requested with NavigateToPost and sync to
+	/// its thread
+	SetPostLocation(common::payloads::SynchronizeToPostRes),
+
+	/// Fetch a single post from any thread, unless already requested
+	FetchPost(u64),
+
 	/// Set or delete the ID of the currently used KeyPair
 	SetKeyID(Option<uuid::Uuid>),
 
@@ -95,6 +112,24 @@ pub enum Request {
 
 	/// Set configs received from the server
 	SetConfigs(common::config::Public),
+
+	/// Set or clear the captcha the user needs to solve
+	SetCaptcha(Option<common::payloads::CaptchaChallenge>),
+
+	/// Set the staff level of the user's public key
+	SetStaffLevel(Option<common::payloads::moderation::ModerationLevel>),
+
+	/// Apply a moderation action to a post or thread
+	ModeratePost(common::payloads::moderation::PostModerationEntry),
+
+	/// Remove the pruned oldest replies of a cyclic thread
+	PrunePosts(Vec<u64>),
+
+	/// Remove a deleted thread and all its posts
+	RemoveThread(u64),
+
+	/// Set the latest full-text search results
+	SetSearchResults(common::payloads::SearchRes),
 }
 
 /// Selective changes of global state to be notified on
@@ -130,6 +165,15 @@ pub enum Change {
 
 	/// Change of the open allocated post ID
 	OpenPostID,
+
+	/// Change of the captcha the user needs to solve
+	Captcha,
+
+	/// Change of the staff level of the user's public key
+	StaffLevel,
+
+	/// Change of the latest full-text search results
+	SearchResults,
 }
 
 /// Abstraction over AgentLink and ComponentLink
@@ -263,6 +307,10 @@ pub struct Agent {
 
 	/// State of synchronization to the current or pending feed
 	feed_sync_state: FeedSyncState,
+
+	/// Posts requested with FetchPost and not received yet. The server does
+	/// not respond to requests for missing posts, so these are not repeated.
+	requested_posts: HashSet<u64>,
 }
 
 impl yew::agent::Agent for Agent {
@@ -286,6 +334,7 @@ impl yew::agent::Agent for Agent {
 			render_task: None,
 			feed_sync_state: FeedSyncState::NotRequested,
 			queued_triggers: Default::default(),
+			requested_posts: Default::default(),
 		}
 	}
 
@@ -323,6 +372,13 @@ impl yew::agent::Agent for Agent {
 				}
 			}
 			NavigateTo { loc, flags } => self.set_location(loc, flags),
+			NavigateToPost(id) => send(MessageType::SynchronizeToPost, &id),
+			SetPostLocation(res) => self.set_post_location(res),
+			FetchPost(id) => {
+				if self.requested_posts.insert(id) {
+					send(MessageType::Post, &id);
+				}
+			}
 			FetchFeed(loc) => {
 				self.try_sync_feed(&loc, SCROLL_TO_FOCUSED);
 			}
@@ -332,6 +388,7 @@ impl yew::agent::Agent for Agent {
 				s.key_pair.store()
 			}),
 			RegisterPost(p) => {
+				self.requested_posts.remove(&p.id);
 				self.trigger(&Change::Thread(p.thread));
 				self.trigger(&Change::Post(p.id));
 				state::get_mut().register_post(p);
@@ -370,6 +427,14 @@ impl yew::agent::Agent for Agent {
 				} if loc.feed.as_u64() == thread.id => {
 					*dst = Some(thread);
 				}
+				// Updated metainformation of the synced thread, such as after
+				// pruning its posts
+				FeedSyncState::Synced { feed, .. }
+					if feed.as_u64() == thread.id =>
+				{
+					self.trigger(&Change::Thread(thread.id));
+					state::get_mut().threads.insert(thread.id, thread);
+				}
 				_ => (),
 			},
 			SetMine(id) => {
@@ -392,6 +457,55 @@ impl yew::agent::Agent for Agent {
 				state::get_mut().configs = c;
 				self.trigger(&Change::Configs);
 			}
+			SetCaptcha(c) => {
+				state::get_mut().captcha = c.map(Rc::new);
+				self.trigger(&Change::Captcha);
+			}
+			SetStaffLevel(l) => {
+				state::get_mut().staff_level = l;
+				self.trigger(&Change::StaffLevel);
+			}
+			SetSearchResults(res) => {
+				state::get_mut().search_results = Some(Rc::new(res));
+				self.trigger(&Change::SearchResults);
+			}
+			ModeratePost(msg) => {
+				let mut s = state::get_mut();
+				if let Some(t) = s.threads.get_mut(&msg.id) {
+					msg.entry.action.apply_to_thread(t);
+					self.trigger(&Change::Thread(msg.id));
+				}
+
+				let action = msg.entry.action;
+				if let Some(p) = s.posts.get_mut(&msg.id) {
+					p.moderate(msg.entry);
+					self.trigger(&Change::Post(msg.id));
+				}
+
+				// Purged posts are closed
+				if action == ModerationAction::PurgePost
+					&& s.open_post_id == Some(msg.id)
+				{
+					s.open_post_id = None;
+					self.trigger(&Change::OpenPostID);
+				}
+			}
+			PrunePosts(ids) => {
+				let mut s = state::get_mut();
+				for id in ids {
+					if let Some(p) = s.remove_post(id) {
+						if let Some(t) = s.threads.get_mut(&p.thread) {
+							t.post_count = t.post_count.saturating_sub(1);
+						}
+						self.trigger(&Change::Thread(p.thread));
+					}
+					if s.open_post_id == Some(id) {
+						s.open_post_id = None;
+						self.trigger(&Change::OpenPostID);
+					}
+				}
+			}
+			RemoveThread(id) => self.remove_thread(id),
 		};
 
 		self.flush_triggers();
@@ -421,6 +535,40 @@ impl Agent {
 		}
 	}
 
+	/// Remove a deleted thread and all its posts and navigate away from it,
+	/// if currently viewed
+	fn remove_thread(&mut self, id: u64) {
+		let mut s = state::get_mut();
+		if s.threads.remove(&id).is_none() {
+			return;
+		}
+
+		let posts: Vec<u64> = s
+			.posts
+			.values()
+			.filter(|p| p.thread == id)
+			.map(|p| p.id)
+			.collect();
+		for p in posts {
+			s.remove_post(p);
+		}
+		if s.open_post_id
+			.map(|p| !s.posts.contains_key(&p))
+			.unwrap_or(false)
+		{
+			s.open_post_id = None;
+			self.trigger(&Change::OpenPostID);
+		}
+		self.trigger(&Change::ThreadList);
+		self.trigger(&Change::Thread(id));
+
+		let viewed = s.location.feed.as_u64() == id;
+		drop(s);
+		if viewed {
+			self.set_location(Location::default(), PUSH_STATE | SET_STATE);
+		}
+	}
+
 	/// Flush queued notifications to clients
 	fn flush_triggers(&mut self) {
 		for id in self.queued_triggers.drain(0..) {
@@ -471,7 +619,9 @@ impl Agent {
 				}
 				if let Entry::Vacant(e) = pages.entry(new_page as u32) {
 					e.insert(false);
-					send(MessageType::Page, &new_page);
+					if flags & SERVER_SYNCED == 0 {
+						send(MessageType::Page, &new_page);
+					}
 					try_to_sync = false;
 				}
 			}
@@ -691,6 +841,35 @@ impl Agent {
 		}
 	}
 
+	/// Sync to the thread of a post resolved by the server. The server follows
+	/// up with the thread metainformation and the post's page.
+	fn set_post_location(
+		&mut self,
+		res: common::payloads::SynchronizeToPostRes,
+	) {
+		let (thread, page) = match res.location {
+			Some(loc) => loc,
+			None => {
+				util::log_and_alert_error(&format!(
+					"post not found: {}",
+					res.id
+				));
+				return;
+			}
+		};
+
+		self.set_location(
+			Location {
+				feed: FeedID::Thread {
+					id: thread,
+					page: page as i32,
+				},
+				focus: Some(Focus::Post(res.id)),
+			},
+			PUSH_STATE | SET_STATE | SCROLL_TO_FOCUSED | SERVER_SYNCED,
+		);
+	}
+
 	/// Fetch feed data from server, if needed.
 	/// Returns, if a fetch is currently in progress.
 	fn try_sync_feed(&mut self, new: &Location, flags: u8) -> bool {
@@ -713,7 +892,9 @@ impl Agent {
 						if let Entry::Vacant(e) = pages.entry(*page) {
 							// Requested another page
 							e.insert(None);
-							send(MessageType::Page, page);
+							if flags & SERVER_SYNCED == 0 {
+								send(MessageType::Page, page);
+							}
 						}
 					}
 					_ => (),
@@ -744,10 +925,12 @@ impl Agent {
 					_ => (),
 				};
 
-				Connection::dispatcher().send(Request::Send {
-					is_open_post_manipulation: false,
-					message: e.finish()?,
-				});
+				if flags & SERVER_SYNCED == 0 {
+					Connection::dispatcher().send(Request::Send {
+						is_open_post_manipulation: false,
+						message: e.finish()?,
+					});
+				}
 				self.feed_sync_state = FeedSyncState::Receiving {
 					loc: new.clone(),
 					flags,
@@ -767,3 +950,13 @@ pub fn navigate_to(loc: Location) {
 		flags: PUSH_STATE | SET_STATE | SCROLL_TO_FOCUSED,
 	});
 }
+
+/// Navigate to the app to a post, resolving its thread and page on the server
+pub fn navigate_to_post(id: u64) {
+	Agent::dispatcher().send(Request::NavigateToPost(id));
+}
+
+/// Fetch a single post from any thread, such as for link previews
+pub fn fetch_post(id: u64) {
+	Agent::dispatcher().send(Request::FetchPost(id));
+}
@@ -145,6 +145,19 @@ impl KeyPair {
 		})
 	}
 
+	/// Sign a handshake challenge issued by the server for the public key
+	/// registered under id
+	pub async fn sign_challenge(
+		&self,
+		id: &uuid::Uuid,
+		nonce: &[u8; 32],
+	) -> util::Result<common::payloads::Signature> {
+		let mut buf = Vec::with_capacity(16 + 32);
+		buf.extend(id.as_bytes());
+		buf.extend(nonce);
+		self.sign(&mut buf).await
+	}
+
 	/// Sign SHA-256 digest of passed buffer
 	pub async fn sign(
 		&self,
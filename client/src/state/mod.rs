@@ -5,7 +5,8 @@ pub mod options;
 pub mod state;
 
 pub use agent::{
-	hook, navigate_to, Agent, Change, Link, Message, Request, StateBridge,
+	fetch_post, hook, navigate_to, navigate_to_post, Agent, Change, Link,
+	Message, Request, StateBridge,
 };
 pub use key_pair::KeyPair;
 pub use location::{FeedID, Focus, Location};
@@ -95,6 +95,15 @@ pub struct State {
 	/// Time correction between the server and client.
 	/// Add to client-generated unix timestamps to correct them.
 	pub time_correction: i32,
+
+	/// Captcha the user needs to solve before creating posts
+	pub captcha: Option<Rc<common::payloads::CaptchaChallenge>>,
+
+	/// Staff level of the user's public key, if any
+	pub staff_level: Option<common::payloads::moderation::ModerationLevel>,
+
+	/// Latest page of full-text search results received from the server
+	pub search_results: Option<Rc<common::payloads::SearchRes>>,
 }
 
 impl State {
@@ -104,6 +113,12 @@ impl State {
 		self.posts.insert(p.id, p);
 	}
 
+	/// Remove a post from the registry and return it, if it was registered
+	pub(super) fn remove_post(&mut self, id: u64) -> Option<Post> {
+		self.posts_by_thread_page.remove_by_value(&id);
+		self.posts.remove(&id)
+	}
+
 	/// Get metainformation of a thread that must be synced.
 	/// Panics on no thread found.
 	pub(super) fn get_synced_thread(&self, id: &u64) -> &Thread {
@@ -98,7 +98,25 @@ impl comp_util::Inner for Inner {
 						}
 					})
 				}
-				<ReplyButton thread=c.props().id />
+				{
+					if c
+						.app_state()
+						.threads
+						.get(&c.props().id)
+						.map(|t| t.locked)
+						.unwrap_or(false)
+					{
+						html! {
+							<b class="admin">
+								{localize!("thread_is_locked")}
+							</b>
+						}
+					} else {
+						html! {
+							<ReplyButton thread=c.props().id />
+						}
+					}
+				}
 			</section>
 		}
 	}
@@ -41,8 +41,9 @@ impl Component for Threads {
 				let s = self.app_state.get();
 				let mut threads: Vec<&Thread> = s.threads.values().collect();
 				// TODO: Different sort orders
-				threads
-					.sort_unstable_by_key(|t| std::cmp::Reverse(t.bumped_on));
+				threads.sort_unstable_by_key(|t| {
+					std::cmp::Reverse((t.sticky, t.bumped_on))
+				});
 
 				let mut w = Vec::with_capacity(threads.len() * 2);
 				for (i, t) in threads.into_iter().enumerate() {
@@ -198,6 +198,14 @@ pub fn alert(msg: &impl std::fmt::Display) {
 	window().alert_with_message(&msg.to_string()).ok();
 }
 
+/// Prompt the user for text input. Returns None, if the prompt was cancelled.
+pub fn prompt(msg: &impl std::fmt::Display) -> Option<String> {
+	window()
+		.prompt_with_message(&msg.to_string())
+		.ok()
+		.flatten()
+}
+
 /// Log error to console and display it in an alert message
 #[cold]
 pub fn log_and_alert_error(err: &impl std::fmt::Display) {
@@ -227,6 +235,18 @@ pub async fn with_logging_async(f: impl std::future::Future<Output = Result>) {
 	}
 }
 
+/// Yield to the browser event loop to let it process any pending events
+pub async fn yield_now() -> Result {
+	wasm_bindgen_futures::JsFuture::from(js_sys::Promise::new(
+		&mut |resolve, _| {
+			// Ignore result
+			window().set_timeout_with_callback(&resolve).ok();
+		},
+	))
+	.await?;
+	Ok(())
+}
+
 /// Format a duration into hours:mins:secs with padding and stripping headers,
 /// as needed
 pub fn format_duration(secs: impl Into<u64>) -> String {
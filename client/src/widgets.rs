@@ -161,6 +161,7 @@ enum Msg {
 	Submit,
 	PostFormState(posting::State),
 	ConnState(connection::State),
+	CaptchaSolved,
 	Rerender,
 	NOP,
 }
@@ -173,6 +174,7 @@ impl Component for NewThreadForm {
 		Self {
 			posting: posting::Agent::bridge(link.callback(|msg| match msg {
 				posting::Response::State(s) => Msg::PostFormState(s),
+				posting::Response::CaptchaSolved => Msg::CaptchaSolved,
 				_ => Msg::NOP,
 			})),
 			connection: connection::Connection::bridge(
@@ -271,8 +273,6 @@ impl Component for NewThreadForm {
 										.name
 										.clone(),
 								},
-								// TODO
-								captcha_solution: vec![],
 							},
 						);
 						Ok(())
@@ -292,6 +292,14 @@ impl Component for NewThreadForm {
 				true
 			}
 			Msg::NOP => false,
+			Msg::CaptchaSolved => {
+				// Retry thread creation interrupted by a captcha request
+				if self.sending && self.expanded {
+					self.sending = false;
+					self.link.send_message(Msg::Submit);
+				}
+				false
+			}
 			Msg::Rerender => true,
 		}
 	}
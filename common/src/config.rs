@@ -3,6 +3,7 @@ use std::collections::HashMap;
 
 /// Upload size constraints
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct UploadMaximums {
 	/// Max size in MB
 	pub size: f64,
@@ -27,6 +28,7 @@ impl Default for UploadMaximums {
 
 /// Upload configurations
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
 pub struct Uploads {
 	/// Use JPEG thumbnails instead of WEBP
 	pub jpeg_thumbnails: bool,
@@ -130,8 +132,26 @@ impl std::fmt::Display for Theme {
 	}
 }
 
+/// Method used to verify clients flagged by antispam are not bots
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AntispamMode {
+	/// Image captcha solved by the user
+	Captcha,
+
+	/// Proof of work solved in the background by the client
+	ProofOfWork,
+}
+
+impl Default for AntispamMode {
+	#[inline]
+	fn default() -> Self {
+		Self::Captcha
+	}
+}
+
 /// Global server configurations exposed to the client
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
 pub struct Public {
 	/// Mark site content for mature audiences
 	pub mature: bool,
@@ -139,7 +159,11 @@ pub struct Public {
 	/// Enable captchas and antispam
 	pub enable_antispam: bool,
 
-	/// Delete unused threads
+	/// Method used to verify clients flagged by antispam are not bots
+	pub antispam_mode: AntispamMode,
+
+	/// Delete threads not bumped for thread_expiry days. Sticky threads are
+	/// never deleted.
 	pub prune_threads: bool,
 
 	/// Days a thread stays unpruned without bumping.
@@ -13,4 +13,4 @@ extern crate num_derive;
 extern crate serde_big_array;
 
 /// Version of common. Increment this on change.
-pub const VERSION: u16 = 1;
+pub const VERSION: u16 = 12;
@@ -28,7 +28,7 @@ pub enum MessageType {
 	/// Insert an image into an open post
 	InsertImage,
 
-	/// Submit captcha or pass result captcha authentication
+	/// Submit a captcha solution or send a captcha challenge or its result
 	Captcha,
 
 	/// Notify client a captcha is required for a previously attempted request
@@ -83,4 +83,40 @@ pub enum MessageType {
 
 	/// Close the currently open post
 	ClosePost,
+
+	/// Request to reclaim an open post lost to disconnection or the result of
+	/// such a request
+	ReclaimOpenPost,
+
+	/// Send a proof of work challenge or submit its solution
+	ProofOfWork,
+
+	/// Request to perform a moderation action or propagation of an applied
+	/// one
+	Moderate,
+
+	/// Notify the client it is banned. The connection is closed right after.
+	Banned,
+
+	/// Oldest replies of a cyclic thread were deleted
+	PrunePosts,
+
+	/// Thread was deleted
+	DeleteThread,
+
+	/// Replace the global server configurations with a JSON-encoded
+	/// configuration object. Requires admin privileges.
+	SetServerConfig,
+
+	/// Server is shutting down. Contains the number of seconds to wait before
+	/// reconnecting.
+	ServerRestarting,
+
+	/// Full-text search of closed post bodies
+	Search,
+
+	/// Synchronize to the thread feed containing a post and fetch the post's
+	/// page. The server responds with the post's location, followed by the
+	/// thread metainformation and page.
+	SynchronizeToPost,
 }
@@ -1,3 +1,4 @@
+pub mod moderation;
 pub mod post_body;
 
 use hex_buffer_serde::{Hex, HexForm};
@@ -25,15 +26,16 @@ pub enum Authorization {
 	/// New public key registration
 	NewPubKey(Vec<u8>),
 
-	/// Key already persisted on the server
-	Saved {
+	/// Key already persisted on the server. The server responds with
+	/// PubKeyStatus::Challenge.
+	Saved(uuid::Uuid),
+
+	/// Response to a PubKeyStatus::Challenge issued by the server
+	SignedChallenge {
 		/// ID of pub key on the server
 		id: uuid::Uuid,
 
-		/// Nonce to hash along with id
-		nonce: [u8; 32],
-
-		/// SHA3-256 signature of id + nonce
+		/// SHA-256 signature of id + nonce supplied by the server
 		signature: Signature,
 	},
 }
@@ -51,11 +53,18 @@ pub struct HandshakeReq {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum PubKeyStatus {
 	/// Key accepted. Handshake complete.
-	Accepted,
+	Accepted {
+		/// Staff level of the public key, if any
+		staff_level: Option<moderation::ModerationLevel>,
+	},
 
 	/// Key already saved in database. Need to confirm it's the same private key
-	/// by sending a HandshakeReq with Authentication::Saved.
-	NeedResend,
+	/// by signing id + nonce and sending a HandshakeReq with
+	/// Authorization::SignedChallenge.
+	///
+	/// The nonce is only valid for this connection, can only be used once and
+	/// expires after a short while.
+	Challenge { nonce: [u8; 32] },
 
 	/// Key not found in database. Need to send Authentication::NewPubKey to
 	/// register it.
@@ -76,7 +85,6 @@ pub struct HandshakeRes {
 pub struct ThreadCreationReq {
 	pub subject: String,
 	pub tags: Vec<String>,
-	pub captcha_solution: Vec<u8>,
 	pub opts: NewPostOpts,
 }
 
@@ -119,6 +127,115 @@ pub struct PostCreationNotification {
 	pub opts: ReplyCreationOpts,
 }
 
+/// Request to reclaim an open post lost to a disconnection
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReclaimOpenPostReq {
+	/// ID of the open post
+	pub id: u64,
+
+	/// Full current text body of the post on the client
+	pub body: String,
+}
+
+/// Result of an open post reclamation request
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ReclaimOpenPostRes {
+	/// Post reclaimed and can be edited further
+	Reclaimed,
+
+	/// Post has already been closed and can not be edited anymore
+	AlreadyClosed,
+
+	/// Post does not exist or was not created with the client's public key
+	NotFound,
+
+	/// Post is still being edited by another connected client
+	InUse,
+}
+
+/// Image from the captcha pool
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct CaptchaImage {
+	/// MIME type of the image
+	pub mime: String,
+
+	/// Raw image data
+	pub data: Vec<u8>,
+}
+
+impl std::fmt::Debug for CaptchaImage {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} ({} bytes)", self.mime, self.data.len())
+	}
+}
+
+/// Captcha to be solved by selecting all images matching a tag
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct CaptchaChallenge {
+	/// Tag all the images of which must be selected
+	pub tag: String,
+
+	/// Images to select from in left to right, top to bottom order
+	pub images: Vec<CaptchaImage>,
+}
+
+/// Captcha message sent from the server
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum CaptchaRes {
+	/// New captcha to solve. Replaces any previous one.
+	Challenge(CaptchaChallenge),
+
+	/// Submitted captcha or proof of work solution was correct. Requests
+	/// previously interrupted with MessageType::NeedCaptcha can be retried.
+	Solved,
+
+	/// Submitted solution was incorrect. A new challenge follows.
+	Invalid,
+
+	/// Submitted proof of work solution was for an expired challenge. No new
+	/// challenge is issued, until the interrupted request is retried.
+	Expired,
+}
+
+/// Hashcash-style proof of work challenge.
+///
+/// Solved by finding a nonce, such that the SHA-256 hash of the salt followed
+/// by the little endian nonce has at least `difficulty` leading zero bits.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct ProofOfWorkChallenge {
+	/// Random salt unique to the challenge
+	pub salt: [u8; 16],
+
+	/// Required number of leading zero bits in the hash
+	pub difficulty: u8,
+}
+
+impl ProofOfWorkChallenge {
+	/// Check, if a nonce solves the challenge
+	pub fn check(&self, nonce: u64) -> bool {
+		use sha2::{Digest, Sha256};
+
+		let mut h = Sha256::new();
+		h.update(&self.salt);
+		h.update(&nonce.to_le_bytes());
+
+		let mut zeros = 0;
+		for b in h.finalize() {
+			zeros += b.leading_zeros();
+			if b != 0 {
+				break;
+			}
+		}
+		zeros >= self.difficulty as u32
+	}
+
+	/// Search for a nonce solving the challenge in the range
+	/// [start, start + count)
+	pub fn solve_range(&self, start: u64, count: u64) -> Option<u64> {
+		(start..start.saturating_add(count)).find(|n| self.check(*n))
+	}
+}
+
 /// Post from a thread
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Post {
@@ -139,6 +256,15 @@ pub struct Post {
 	pub body: Arc<Node>,
 
 	pub image: Option<Image>,
+
+	/// Post has been deleted by a staff member
+	pub deleted: bool,
+
+	/// Post is hidden from everyone except its author
+	pub shadow_binned: bool,
+
+	/// Public log of moderation actions performed on the post
+	pub moderation_log: Vec<moderation::ModerationEntry>,
 }
 
 impl Post {
@@ -159,6 +285,9 @@ impl Post {
 			open: true,
 			body: Default::default(),
 			image: None,
+			deleted: false,
+			shadow_binned: false,
+			moderation_log: Default::default(),
 			sage: opts.sage,
 			name: opts.post_opts.name,
 			trip: opts.post_opts.trip,
@@ -208,6 +337,16 @@ pub struct Thread {
 
 	/// Number of images in the thread
 	pub image_count: u64,
+
+	/// Thread does not accept any new replies
+	pub locked: bool,
+
+	/// Thread is pinned to the top of the thread index
+	pub sticky: bool,
+
+	/// Oldest replies of the thread are deleted, once the thread exceeds the
+	/// configured post limit
+	pub cyclic: bool,
 }
 
 impl Thread {
@@ -228,6 +367,9 @@ impl Thread {
 			bumped_on: created_on,
 			post_count: 1,
 			image_count: 0,
+			locked: false,
+			sticky: false,
+			cyclic: false,
 		}
 	}
 }
@@ -247,6 +389,56 @@ pub struct ImmutablePage {
 	pub posts: Vec<Post>,
 }
 
+/// Location of a post requested with MessageType::SynchronizeToPost
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SynchronizeToPostRes {
+	/// ID of the requested post
+	pub id: u64,
+
+	/// Thread and page of the post, if it exists
+	pub location: Option<(u64, u32)>,
+}
+
+/// Full-text search of closed post bodies
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchReq {
+	/// Search query in web search engine syntax
+	pub query: String,
+
+	/// Only search threads with this tag
+	pub tag: Option<String>,
+
+	/// Only search this thread
+	pub thread: Option<u64>,
+
+	/// Page of results to return, starting from 0
+	pub page: u32,
+}
+
+/// Page of search results, ordered by relevance
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchRes {
+	pub query: String,
+	pub page: u32,
+
+	/// Matched posts. Thread and page of each post locate it.
+	pub posts: Vec<Post>,
+
+	/// More pages of results exist
+	pub has_more: bool,
+}
+
+/// Response to a full-text search request sent over a websocket
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SearchReply {
+	/// Page of search results
+	Results(SearchRes),
+
+	/// Too many searches were performed recently. The request was not
+	/// processed and can be retried later.
+	RateLimited,
+}
+
 /// Supported file types
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FileType {
@@ -344,3 +536,24 @@ pub struct InsertImage {
 	pub post: u64,
 	pub image: Image,
 }
+
+#[cfg(test)]
+mod test {
+	use super::ProofOfWorkChallenge;
+
+	#[test]
+	fn proof_of_work() {
+		let mut ch = ProofOfWorkChallenge {
+			salt: [7; 16],
+			difficulty: 0,
+		};
+		assert!(ch.check(0));
+
+		ch.difficulty = 8;
+		let nonce = ch.solve_range(0, 1 << 16).unwrap();
+		assert!(ch.check(nonce));
+
+		ch.difficulty = 255;
+		assert!(!ch.check(nonce));
+	}
+}
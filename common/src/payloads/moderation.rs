@@ -0,0 +1,228 @@
+use super::{post_body::Node, Post, Thread};
+use serde::{Deserialize, Serialize};
+
+/// Permission level of a staff member. Each level includes all permissions of
+/// the levels below it.
+#[repr(u8)]
+#[derive(
+	Serialize,
+	Deserialize,
+	FromPrimitive,
+	Copy,
+	Clone,
+	Eq,
+	PartialEq,
+	Ord,
+	PartialOrd,
+	Debug,
+)]
+pub enum ModerationLevel {
+	/// Can delete and spoiler post images and delete posts
+	Janitor = 1,
+
+	/// Can additionally purge and shadow bin posts, lock, sticky and cycle
+	/// threads and ban posters
+	Moderator,
+
+	/// Full access to all moderation actions and staff management
+	Admin,
+}
+
+/// Action performed on a post or thread by a staff member
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+	/// Mark post as deleted. Its contents are kept for transparency.
+	DeletePost,
+
+	/// Remove the image from a post
+	DeleteImage,
+
+	/// Force the image of a post to be spoilered
+	SpoilerImage,
+
+	/// Delete post and irreversibly remove its text body and image
+	PurgePost,
+
+	/// Hide post from everyone except its author
+	ShadowBinPost,
+
+	/// Set or unset thread as locked, preventing any new replies.
+	/// Can only target the OP of the thread.
+	LockThread(bool),
+
+	/// Set or unset thread as sticky, pinning it to the top of the thread
+	/// index. Can only target the OP of the thread.
+	StickyThread(bool),
+
+	/// Set or unset thread as cyclic, deleting its oldest replies past the
+	/// post limit. Can only target the OP of the thread.
+	CyclicThread(bool),
+
+	/// Ban the public key and IP the post was created with for the duration
+	/// of the action
+	BanPoster,
+}
+
+impl ModerationAction {
+	/// Minimum staff level required to perform the action
+	pub fn required_level(&self) -> ModerationLevel {
+		use ModerationAction::*;
+
+		match self {
+			DeletePost | DeleteImage | SpoilerImage => ModerationLevel::Janitor,
+			PurgePost | ShadowBinPost | LockThread(_) | StickyThread(_)
+			| CyclicThread(_) | BanPoster => ModerationLevel::Moderator,
+		}
+	}
+
+	/// Apply the action to a post
+	pub fn apply_to_post(&self, p: &mut Post) {
+		use ModerationAction::*;
+
+		match self {
+			DeletePost => p.deleted = true,
+			DeleteImage => p.image = None,
+			SpoilerImage => {
+				if let Some(img) = &mut p.image {
+					img.spoilered = true;
+				}
+			}
+			PurgePost => {
+				p.deleted = true;
+				p.open = false;
+				p.body = Node::Empty.into();
+				p.image = None;
+			}
+			ShadowBinPost => p.shadow_binned = true,
+			LockThread(_) | StickyThread(_) | CyclicThread(_) | BanPoster => (),
+		}
+	}
+
+	/// Apply the action to a thread
+	pub fn apply_to_thread(&self, t: &mut Thread) {
+		use ModerationAction::*;
+
+		match self {
+			LockThread(locked) => t.locked = *locked,
+			StickyThread(sticky) => t.sticky = *sticky,
+			CyclicThread(cyclic) => t.cyclic = *cyclic,
+			_ => (),
+		}
+	}
+}
+
+/// Request to perform a moderation action on a post or the thread of an OP
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct PostModeration {
+	/// ID of the target post
+	pub id: u64,
+
+	/// Action to perform
+	pub action: ModerationAction,
+
+	/// Duration of the action in seconds, if applicable
+	pub length: u32,
+
+	/// Reason for the action to display in the moderation log
+	pub reason: String,
+}
+
+/// Entry in the public moderation log of a post
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct ModerationEntry {
+	/// Performed action
+	pub action: ModerationAction,
+
+	/// Level of the staff member, that performed the action, or None, if it
+	/// was performed by the post's author
+	pub staff_level: Option<ModerationLevel>,
+
+	/// Title of the staff member, that performed the action, if any
+	pub staff_title: Option<String>,
+
+	/// Duration of the action in seconds, if applicable
+	pub length: u32,
+
+	/// Reason for the action
+	pub reason: String,
+
+	/// Unix timestamp of the action
+	pub created_on: u32,
+}
+
+/// Moderation log entry appended to a post. Sent from the server to propagate
+/// an applied moderation action.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct PostModerationEntry {
+	/// ID of the target post
+	pub id: u64,
+
+	pub entry: ModerationEntry,
+}
+
+/// Notice sent to a client, that is banned, before disconnecting it
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct BanNotice {
+	/// Reason for the ban
+	pub reason: String,
+
+	/// Unix timestamp of ban expiry
+	pub expires: u32,
+}
+
+impl Post {
+	/// Apply a moderation action to the post and append it to the post's
+	/// moderation log
+	pub fn moderate(&mut self, entry: ModerationEntry) {
+		entry.action.apply_to_post(self);
+		self.moderation_log.push(entry);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn required_level() {
+		use ModerationAction::*;
+
+		assert!(ModerationLevel::Admin > ModerationLevel::Moderator);
+		assert!(DeletePost.required_level() <= ModerationLevel::Janitor);
+		assert!(PurgePost.required_level() > ModerationLevel::Janitor);
+		assert!(LockThread(true).required_level() > ModerationLevel::Janitor);
+		assert!(BanPoster.required_level() > ModerationLevel::Janitor);
+		assert!(StickyThread(true).required_level() > ModerationLevel::Janitor);
+		assert!(CyclicThread(true).required_level() > ModerationLevel::Janitor);
+	}
+
+	#[test]
+	fn purge_post() {
+		let mut p = Post::new_op(1, 0, Default::default());
+		p.body = Node::Text("foo".into()).into();
+		p.moderate(ModerationEntry {
+			action: ModerationAction::PurgePost,
+			staff_level: Some(ModerationLevel::Moderator),
+			staff_title: None,
+			length: 0,
+			reason: "spam".into(),
+			created_on: 1,
+		});
+		assert!(p.deleted);
+		assert!(!p.open);
+		assert_eq!(*p.body, Node::Empty);
+		assert_eq!(p.moderation_log.len(), 1);
+	}
+
+	#[test]
+	fn thread_flags() {
+		let mut t = Thread::new(1, 0, "foo".into(), vec![]);
+		ModerationAction::StickyThread(true).apply_to_thread(&mut t);
+		ModerationAction::CyclicThread(true).apply_to_thread(&mut t);
+		ModerationAction::DeletePost.apply_to_thread(&mut t);
+		assert!(t.sticky);
+		assert!(t.cyclic);
+		assert!(!t.locked);
+	}
+}
@@ -407,6 +407,136 @@ pub enum Command {
 	PCount(u64),
 }
 
+impl Command {
+	/// Format the command and its result as text and return it along with the
+	/// CSS class to highlight it with, if any.
+	///
+	/// Countdowns are formatted only as their duration.
+	pub fn format(&self) -> (String, &'static str) {
+		use Command::*;
+
+		let mut class = "";
+		let text = match self {
+			Countdown { secs, .. } => format!("#countdown({})", secs),
+			Autobahn(hours) => format!("#autobahn({})", hours),
+			EightBall(msg) => format!("#8ball ({})", msg),
+			Flip(b) => format!("#flip ({})", if *b { "flap" } else { "flop" }),
+			Pyu(n) => format!("#pyu ({})", n),
+			PCount(n) => format!("#pcount ({})", n),
+			Dice {
+				offset,
+				faces,
+				results,
+			} => {
+				let mut s = format_dice(*offset, *faces, results.len());
+				let sign = if offset < &0 { '-' } else { '+' };
+
+				s += " (";
+				let mut sum = 0_i32;
+				for (i, r) in results.iter().enumerate() {
+					if i != 0 {
+						s += " + ";
+					}
+					sum += *r as i32;
+					s += &r.to_string();
+				}
+				if offset != &0 {
+					sum += *offset as i32;
+					s += &format!(" {} {}", sign, offset.abs());
+				}
+				if results.len() != 1 || offset != &0 {
+					s += &format!(" = {}", sum);
+				}
+				s.push(')');
+
+				let max_raw = *faces as i32 * results.len() as i32;
+				let max = max_raw + *offset as i32;
+				// No special formatting for small rolls
+				if max_raw >= 10 && faces != &1 {
+					if sum == max {
+						class = "max-roll";
+					} else if sum as usize == results.len() {
+						class = "shit-roll";
+					} else if sum == 69 || sum == 6969 {
+						class = "lewd-roll";
+					} else if {
+						let mut n = sum;
+						let digit = sum % 10;
+						let mut repeating = false;
+						loop {
+							n /= 10;
+							if n == 0 {
+								repeating = true;
+								break;
+							}
+							if n % 10 != digit {
+								break;
+							}
+						}
+						repeating
+					} {
+						match sum {
+							11..=99 => {
+								class = "dubs-roll";
+							}
+							111..=999 => {
+								class = "trips-roll";
+							}
+							1111..=9999 => {
+								class = "quads-roll";
+							}
+							_ => (),
+						}
+					}
+				}
+
+				s
+			}
+		};
+		(text, class)
+	}
+}
+
+impl PendingNode {
+	/// Format the pending node as text with placeholders for any pending
+	/// results
+	pub fn format(&self) -> String {
+		use PendingNode::*;
+
+		match self {
+			Flip => "#flip (?)".into(),
+			EightBall => "#8ball (?)".into(),
+			Pyu => "#pyu (?)".into(),
+			PCount => "#pcount (?)".into(),
+			Countdown(n) => format!("#countdown({})", n),
+			Autobahn(n) => format!("#autobahn({})", n),
+			PostLink(id) => format!(">>{}", id),
+			Dice {
+				offset,
+				faces,
+				rolls,
+			} => {
+				let mut s = format_dice(*offset, *faces, *rolls as usize);
+				s += " (?)";
+				s
+			}
+		}
+	}
+}
+
+/// Format the parameters of a dice roll command
+fn format_dice(offset: i16, faces: u16, rolls: usize) -> String {
+	let mut s = String::from("#");
+	if rolls > 1 {
+		s += &rolls.to_string();
+	}
+	s += &format!("d{}", faces);
+	if offset != 0 {
+		s += &format!("{}{}", if offset < 0 { '-' } else { '+' }, offset.abs());
+	}
+	s
+}
+
 /// Embedded content providers
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Copy)]
 #[serde(rename_all = "snake_case")]
@@ -940,4 +1070,46 @@ mod test {
 			)
 		}
 	}
+
+	mod commands {
+		use super::super::*;
+
+		#[test]
+		fn format_dice() {
+			let (text, class) = Command::Dice {
+				offset: -2,
+				faces: 6,
+				results: vec![6, 6],
+			}
+			.format();
+			assert_eq!(text, "#2d6-2 (6 + 6 - 2 = 10)");
+			assert_eq!(class, "max-roll");
+		}
+
+		#[test]
+		fn format_dubs() {
+			let (text, class) = Command::Dice {
+				offset: 0,
+				faces: 100,
+				results: vec![44],
+			}
+			.format();
+			assert_eq!(text, "#d100 (44)");
+			assert_eq!(class, "dubs-roll");
+		}
+
+		#[test]
+		fn format_pending() {
+			assert_eq!(
+				PendingNode::Dice {
+					offset: 1,
+					faces: 20,
+					rolls: 3,
+				}
+				.format(),
+				"#3d20+1 (?)"
+			);
+			assert_eq!(PendingNode::PostLink(12).format(), ">>12");
+		}
+	}
 }
@@ -0,0 +1,107 @@
+-- Staff accounts and their moderation levels.
+-- Levels: 1 - janitor, 2 - moderator, 3 - admin
+create table staff (
+	public_key bigint primary key references public_keys on delete cascade,
+	level smallint not null check (level between 1 and 3),
+	created_on timestamptz_auto_now
+);
+
+alter table posts
+	add column deleted bool not null default false,
+	add column shadow_binned bool not null default false;
+
+alter table threads
+	add column locked bool not null default false;
+
+-- Encode post row to json
+create or replace function encode(p posts)
+returns jsonb
+language plpgsql stable parallel safe strict
+as $$
+declare
+	data jsonb;
+	img images;
+begin
+	data = jsonb_build_object(
+		'id', p.id,
+		'thread', p.thread,
+		'page', p.page,
+
+		'created_on', to_unix(p.created_on),
+		'open', p.open,
+
+		'sage', p.sage,
+		'name', p.name,
+		'trip', p.trip,
+		'flag', p.flag,
+
+		'body', p.body,
+		'image', null,
+
+		'deleted', p.deleted,
+		'shadow_binned', p.shadow_binned
+	);
+
+	if p.image is not null then
+		select i.* into img
+			from images i
+			where i.sha1 = p.image;
+
+		data = data || jsonb_build_object(
+			'image', jsonb_build_object(
+				'name', p.image_name,
+				'spoilered', p.image_spoilered,
+
+				'sha1', encode(img.sha1, 'hex'),
+				'md5', encode(img.md5, 'hex'),
+
+				'audio', img.audio,
+				'video', img.video,
+
+				'file_type', img.file_type,
+				'thumb_type', img.thumb_type,
+
+				'width', img.width,
+				'height', img.height,
+				'thumb_width', img.thumb_width,
+				'thumb_height', img.thumb_height,
+
+				'size', img.size,
+				'duration', img.duration,
+
+				'title', img.title,
+				'artist', img.artist
+			)
+		);
+	end if;
+
+	return data;
+end;
+$$;
+
+-- Encode thread column into struct
+create or replace function encode(t threads, page bigint, page_count bigint)
+returns jsonb
+language plpgsql stable parallel safe strict
+as $$
+begin
+	return jsonb_build_object(
+		'id', t.id,
+		'page', page,
+		'page_count', page_count,
+
+		'subject', t.subject,
+		'tags', t.tags,
+		'locked', t.locked,
+
+		'bumped_on', to_unix(t.bumped_on),
+		'created_on', to_unix(t.created_on),
+		'post_count', post_count(t.id),
+		'image_count', (
+			select count(*)
+			from posts p
+			where p.thread = t.id and p.image is not null
+		)
+	);
+end;
+$$;
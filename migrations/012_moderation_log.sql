@@ -0,0 +1,120 @@
+alter table staff
+	add column title varchar(100);
+
+-- Public log of moderation actions performed on posts.
+-- Actions on threads are logged on their OPs.
+create table moderation_log (
+	id bigserial primary key,
+	post bigint not null references posts on delete cascade,
+
+	-- JSON-encoded ModerationAction
+	action jsonb not null,
+
+	-- Staff member, that performed the action.
+	-- Null, if performed by the post's author.
+	staff bigint references public_keys on delete set null,
+
+	-- Staff level and title at the time of the action
+	staff_level smallint check (staff_level between 1 and 3),
+	staff_title varchar(100),
+
+	-- Duration of the action in seconds, if applicable
+	length bigint not null default 0 check (length >= 0),
+
+	reason varchar(200) not null default '',
+	created_on timestamptz_auto_now
+);
+
+create index moderation_log_post_idx on moderation_log (post);
+
+-- Encode moderation log entry row to json
+create or replace function encode(l moderation_log)
+returns jsonb
+language plpgsql stable parallel safe strict
+as $$
+begin
+	return jsonb_build_object(
+		'action', l.action,
+		'staff_level', case l.staff_level
+			when 1 then 'Janitor'
+			when 2 then 'Moderator'
+			when 3 then 'Admin'
+		end,
+		'staff_title', l.staff_title,
+		'length', l.length,
+		'reason', l.reason,
+		'created_on', to_unix(l.created_on)
+	);
+end;
+$$;
+
+-- Encode post row to json
+create or replace function encode(p posts)
+returns jsonb
+language plpgsql stable parallel safe strict
+as $$
+declare
+	data jsonb;
+	img images;
+begin
+	data = jsonb_build_object(
+		'id', p.id,
+		'thread', p.thread,
+		'page', p.page,
+
+		'created_on', to_unix(p.created_on),
+		'open', p.open,
+
+		'sage', p.sage,
+		'name', p.name,
+		'trip', p.trip,
+		'flag', p.flag,
+
+		'body', p.body,
+		'image', null,
+
+		'deleted', p.deleted,
+		'shadow_binned', p.shadow_binned,
+		'moderation_log', (
+			select coalesce(jsonb_agg(encode(l) order by l.id), '[]')
+			from moderation_log l
+			where l.post = p.id
+		)
+	);
+
+	if p.image is not null then
+		select i.* into img
+			from images i
+			where i.sha1 = p.image;
+
+		data = data || jsonb_build_object(
+			'image', jsonb_build_object(
+				'name', p.image_name,
+				'spoilered', p.image_spoilered,
+
+				'sha1', encode(img.sha1, 'hex'),
+				'md5', encode(img.md5, 'hex'),
+
+				'audio', img.audio,
+				'video', img.video,
+
+				'file_type', img.file_type,
+				'thumb_type', img.thumb_type,
+
+				'width', img.width,
+				'height', img.height,
+				'thumb_width', img.thumb_width,
+				'thumb_height', img.thumb_height,
+
+				'size', img.size,
+				'duration', img.duration,
+
+				'title', img.title,
+				'artist', img.artist
+			)
+		);
+	end if;
+
+	return data;
+end;
+$$;
@@ -0,0 +1,23 @@
+-- IP the post was created from. Used for IP bans. Never sent to clients.
+alter table posts
+	add column ip inet;
+
+-- Bans of public keys and IP addresses or ranges
+create table bans (
+	id bigserial primary key,
+
+	-- Exactly one of public_key and ip must be set
+	public_key bigint references public_keys on delete cascade,
+	ip inet,
+	constraint target_validity check ((public_key is null) != (ip is null)),
+
+	-- Staff member, that issued the ban
+	staff bigint references public_keys on delete set null,
+
+	reason varchar(200) not null default '',
+	created_on timestamptz_auto_now
+)
+inherits (expiries);
+create index bans_expires_idx on bans (expires);
+create index bans_public_key_idx on bans (public_key);
+create index bans_ip_idx on bans using gist (ip inet_ops);
@@ -0,0 +1,62 @@
+alter table threads
+	add column sticky bool not null default false,
+	add column cyclic bool not null default false;
+
+-- Assign pages by the fill of the last page instead of the post count, as
+-- cyclic threads delete their oldest posts
+create or replace function before_posts_insert()
+returns trigger
+language plpgsql
+as $$
+declare
+	last_page int;
+	posts_on_last_page bigint;
+begin
+	select p.page, count(*) into last_page, posts_on_last_page
+		from posts p
+		where p.thread = new.thread
+		group by p.page
+		order by p.page desc
+		limit 1;
+	new.page = case
+		when last_page is null then 0
+		when posts_on_last_page >= 100 then last_page + 1
+		else last_page
+	end;
+
+	if not new.sage then
+		call bump_thread(new.thread);
+	end if;
+
+	return new;
+end;
+$$;
+
+-- Encode thread column into struct
+create or replace function encode(t threads, page bigint, page_count bigint)
+returns jsonb
+language plpgsql stable parallel safe strict
+as $$
+begin
+	return jsonb_build_object(
+		'id', t.id,
+		'page', page,
+		'page_count', page_count,
+
+		'subject', t.subject,
+		'tags', t.tags,
+		'locked', t.locked,
+		'sticky', t.sticky,
+		'cyclic', t.cyclic,
+
+		'bumped_on', to_unix(t.bumped_on),
+		'created_on', to_unix(t.created_on),
+		'post_count', post_count(t.id),
+		'image_count', (
+			select count(*)
+			from posts p
+			where p.thread = t.id and p.image is not null
+		)
+	);
+end;
+$$;
@@ -0,0 +1 @@
+create index threads_bumped_on_idx on threads (bumped_on);
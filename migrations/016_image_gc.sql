@@ -0,0 +1,4 @@
+-- Protects freshly inserted images from garbage collection, before any post or
+-- pending image references them
+alter table images
+	add column created_on timestamptz_auto_now;
@@ -0,0 +1,16 @@
+-- Notify running servers of thread deletions, so deletions performed outside
+-- of them are propagated to clients
+create or replace function notify_thread_deletions()
+returns trigger
+language plpgsql
+as $$
+begin
+	perform pg_notify('thread.deleted', old.id::text);
+	return old;
+end;
+$$;
+
+create trigger notify_thread_deletions
+after delete on threads
+for each row
+execute function notify_thread_deletions();
@@ -0,0 +1,7 @@
+-- Full-text search index of closed post bodies.
+-- Set on post closure. Posts closed before this migration are indexed by
+-- running `shamichan index-search`.
+alter table posts
+	add column search tsvector;
+
+create index posts_search_idx on posts using gin (search);
@@ -0,0 +1,4 @@
+-- Shadow binned posts are fetched separately from their pages for their
+-- authors and staff
+create index posts_shadow_binned_idx on posts (thread)
+	where shadow_binned;
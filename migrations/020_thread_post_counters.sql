@@ -0,0 +1,84 @@
+-- Cache the post count and last page fill of threads instead of aggregating
+-- the posts of a thread on every insert
+alter table threads
+	add column post_count bigint not null default 0,
+	add column last_page int not null default 0,
+	add column posts_on_last_page int not null default 0;
+
+update threads t
+set
+	post_count = c.post_count,
+	last_page = c.last_page
+from (
+	select thread, count(*) post_count, max(page) last_page
+	from posts
+	group by thread
+) c
+where c.thread = t.id;
+
+update threads t
+set posts_on_last_page = (
+	select count(*)
+	from posts p
+	where p.thread = t.id and p.page = t.last_page
+);
+
+create or replace function post_count(thread bigint)
+returns bigint
+language sql stable parallel safe strict
+as $$
+	select t.post_count
+	from threads t
+	where t.id = post_count.thread;
+$$;
+
+-- Assign pages by the cached fill of the last page. Also serializes concurrent
+-- inserts into the same thread by locking its row.
+create or replace function before_posts_insert()
+returns trigger
+language plpgsql
+as $$
+begin
+	update threads t
+	set
+		post_count = t.post_count + 1,
+		last_page = case
+			when t.posts_on_last_page >= 100 then t.last_page + 1
+			else t.last_page
+		end,
+		posts_on_last_page = case
+			when t.posts_on_last_page >= 100 then 1
+			else t.posts_on_last_page + 1
+		end,
+		bumped_on = case
+			when new.sage then t.bumped_on
+			else now()
+		end
+	where t.id = new.thread
+	returning t.last_page into new.page;
+
+	return new;
+end;
+$$;
+
+create or replace function after_posts_delete()
+returns trigger
+language plpgsql
+as $$
+begin
+	update threads t
+	set
+		post_count = t.post_count - 1,
+		posts_on_last_page = case
+			when old.page = t.last_page then t.posts_on_last_page - 1
+			else t.posts_on_last_page
+		end
+	where t.id = old.thread;
+
+	return null;
+end;
+$$;
+
+create trigger after_posts_delete
+after delete on posts
+for each row execute procedure after_posts_delete();
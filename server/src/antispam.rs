@@ -0,0 +1,195 @@
+use crate::{
+	config::{self, Config},
+	db,
+	util::DynResult,
+};
+use std::{
+	collections::HashMap,
+	net::IpAddr,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+/// Spam score, after exceeding which, a captcha solution is requested
+const SPAM_DETECTION_THRESHOLD: Duration = Duration::from_secs(60);
+
+lazy_static::lazy_static! {
+	/// Spam score increments by public key private ID buffered till the next
+	/// flush to the DB
+	static ref BUFFER: Mutex<HashMap<u64, Duration>> = Default::default();
+
+	/// Spam score expiry times of anonymous clients by IP address. Only kept
+	/// in memory.
+	static ref IP_SCORES: Mutex<HashMap<IpAddr, Instant>> = Default::default();
+
+	/// Search score expiry times by public key private ID. Only kept in
+	/// memory and tracked separately from the spam score, so searching does
+	/// not affect posting.
+	static ref SEARCH_SCORES: Mutex<HashMap<u64, Instant>> =
+		Default::default();
+}
+
+/// Client action incurring a spam score increment
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+	/// Modification of the post body affecting a number of Unicode characters
+	Characters(usize),
+
+	/// Creation of a thread or reply
+	PostCreation,
+
+	/// Full-text search of post bodies
+	Search,
+}
+
+/// Return the configured spam score of an action
+fn action_score(conf: &Config, action: Action) -> Duration {
+	let s = &conf.spam_scores;
+	Duration::from_millis(match action {
+		Action::Characters(n) => n * s.character,
+		Action::PostCreation => s.post_creation,
+		Action::Search => s.search,
+	} as u64)
+}
+
+/// Increment the spam score of a public key by the configured score for an
+/// action.
+///
+/// Spam scores are sliding windows, that expire over time and are measured in
+/// milliseconds.
+pub fn increment_spam_score(pub_key: u64, action: Action) {
+	let conf = config::get();
+	if !conf.public.enable_antispam {
+		return;
+	}
+
+	*BUFFER.lock().unwrap().entry(pub_key).or_default() +=
+		action_score(&conf, action);
+}
+
+/// Increment the in-memory spam score of an anonymous client's IP address by
+/// the configured score for an action and return, if the score now exceeds
+/// the spam detection threshold.
+pub fn increment_ip_spam_score(ip: IpAddr, action: Action) -> bool {
+	increment_in_memory(&IP_SCORES, ip, action)
+}
+
+/// Increment the in-memory search score of a public key by the configured
+/// score for a search and return, if the score now exceeds the spam detection
+/// threshold
+pub fn increment_search_score(pub_key: u64) -> bool {
+	increment_in_memory(&SEARCH_SCORES, pub_key, Action::Search)
+}
+
+/// Increment an in-memory score by the configured score for an action and
+/// return, if the score now exceeds the spam detection threshold
+fn increment_in_memory<K>(
+	scores: &Mutex<HashMap<K, Instant>>,
+	key: K,
+	action: Action,
+) -> bool
+where
+	K: Eq + std::hash::Hash,
+{
+	let conf = config::get();
+	if !conf.public.enable_antispam {
+		return false;
+	}
+
+	let now = Instant::now();
+	let mut scores = scores.lock().unwrap();
+	let expires = scores.entry(key).or_insert(now);
+	*expires = std::cmp::max(*expires, now) + action_score(&conf, action);
+	*expires - now > SPAM_DETECTION_THRESHOLD
+}
+
+/// Remove expired in-memory spam scores of IP addresses and search scores of
+/// public keys
+pub fn expire_in_memory_scores() {
+	let now = Instant::now();
+	IP_SCORES
+		.lock()
+		.unwrap()
+		.retain(|_, expires| *expires > now);
+	SEARCH_SCORES
+		.lock()
+		.unwrap()
+		.retain(|_, expires| *expires > now);
+}
+
+/// Return, if the public key needs to solve a captcha to proceed with usage of
+/// server resources.
+///
+/// A captcha is required, if none was solved in the last 3 hours or the spam
+/// score exceeds the detection threshold.
+pub async fn need_captcha(pub_key: u64) -> DynResult<bool> {
+	if !config::get().public.enable_antispam {
+		return Ok(false);
+	}
+	if !db::solved_captcha_recently(pub_key).await? {
+		return Ok(true);
+	}
+	is_spamming(pub_key).await
+}
+
+/// Return, if the spam score of a public key exceeds the spam detection
+/// threshold
+pub async fn is_spamming(pub_key: u64) -> DynResult<bool> {
+	if !config::get().public.enable_antispam {
+		return Ok(false);
+	}
+	Ok(spam_score(pub_key).await? > SPAM_DETECTION_THRESHOLD)
+}
+
+/// Return the current spam score of a public key including any buffered
+/// increments
+async fn spam_score(pub_key: u64) -> DynResult<Duration> {
+	Ok(Duration::from_millis(db::get_spam_score(pub_key).await?)
+		+ BUFFER
+			.lock()
+			.unwrap()
+			.get(&pub_key)
+			.copied()
+			.unwrap_or_default())
+}
+
+/// Return the proof of work difficulty for a public key.
+///
+/// Each multiple of the spam detection threshold in the spam score adds a bit
+/// of difficulty and thus doubles the expected work.
+pub async fn proof_of_work_difficulty(pub_key: u64) -> DynResult<u8> {
+	let conf = config::get();
+	let c = &conf.proof_of_work;
+	let extra = spam_score(pub_key).await?.as_millis()
+		/ SPAM_DETECTION_THRESHOLD.as_millis();
+	Ok(std::cmp::min(
+		c.base_difficulty as u128 + extra,
+		c.max_difficulty as u128,
+	) as u8)
+}
+
+/// Record a solved captcha and reset the spam score of the public key
+pub async fn record_valid_captcha(pub_key: u64) -> DynResult {
+	BUFFER.lock().unwrap().remove(&pub_key);
+	db::record_valid_captcha(pub_key).await?;
+	Ok(())
+}
+
+/// Flush buffered spam score increments to the DB.
+///
+/// On failure the increments are merged back into the buffer to be retried on
+/// the next flush.
+pub async fn flush_spam_scores() -> DynResult {
+	let buf = std::mem::take(&mut *BUFFER.lock().unwrap());
+	if buf.is_empty() {
+		return Ok(());
+	}
+	if let Err(err) = db::flush_spam_scores(buf.clone()).await {
+		let mut b = BUFFER.lock().unwrap();
+		for (pub_key, inc) in buf {
+			*b.entry(pub_key).or_default() += inc;
+		}
+		return Err(err);
+	}
+	Ok(())
+}
@@ -0,0 +1,223 @@
+use crate::{
+	antispam, body,
+	feeds::{IndexFeed, ReadMeta, ReadPage, ReadThreads, ThreadFeed},
+	mt_context::{self, MTAddr},
+	registry::{self, Registry},
+	search, util,
+};
+use actix::prelude::*;
+use actix_web::{
+	error::{
+		ErrorBadRequest, ErrorInternalServerError, ErrorNotFound,
+		ErrorTooManyRequests,
+	},
+	get, web, HttpRequest, HttpResponse, Result, Scope,
+};
+use common::payloads::{ImmutablePage, Post, SearchReq, ThreadWithPosts};
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+/// Read-only JSON API served from the feeds' in-memory data
+pub fn scope() -> Scope {
+	web::scope("/api/json")
+		.service(get_threads)
+		.service(get_thread)
+		.service(get_page)
+		.service(get_post)
+		.service(get_search)
+}
+
+/// Encode a response payload as JSON with an ETag
+fn respond<T: Serialize>(
+	req: &HttpRequest,
+	payload: &T,
+) -> Result<HttpResponse> {
+	// Round trip through a Value, which stores objects in BTreeMaps, to sort
+	// map keys, so the ETag does not depend on HashMap iteration order
+	let val =
+		serde_json::to_value(payload).map_err(ErrorInternalServerError)?;
+	Ok(util::respond_with_etag(
+		req,
+		"application/json",
+		serde_json::to_vec(&val).map_err(ErrorInternalServerError)?,
+	))
+}
+
+/// Get the feed of an existing thread
+async fn get_feed(
+	registry: &Addr<Registry>,
+	id: u64,
+) -> Result<MTAddr<ThreadFeed>> {
+	registry
+		.send(registry::GetFeed(id))
+		.await
+		.map_err(ErrorInternalServerError)?
+		.map_err(ErrorNotFound)
+}
+
+/// Read a thread page from its feed, excluding any shadow binned posts
+async fn read_page(
+	feed: &MTAddr<ThreadFeed>,
+	page: u32,
+) -> Result<Option<Vec<Post>>> {
+	let (send, receive) = oneshot::channel();
+	feed.do_send(ReadPage { page, reply: send });
+	Ok(receive.await.map_err(ErrorInternalServerError)?.map(
+		|(_, mut posts)| {
+			posts.retain(|p| !p.shadow_binned);
+			posts
+		},
+	))
+}
+
+/// All threads on the thread index with their OPs and latest replies
+#[get("/threads")]
+async fn get_threads(
+	req: HttpRequest,
+	index_feed: web::Data<MTAddr<IndexFeed>>,
+) -> Result<HttpResponse> {
+	let (send, receive) = oneshot::channel();
+	index_feed.do_send(ReadThreads(send));
+	let mut threads = receive
+		.await
+		.map_err(ErrorInternalServerError)?
+		.into_iter()
+		.map(|(thread, posts)| ThreadWithPosts {
+			thread,
+			posts: posts
+				.into_iter()
+				.filter(|p| !p.shadow_binned)
+				.map(|p| (p.id, p))
+				.collect(),
+		})
+		.collect::<Vec<_>>();
+	threads.sort_unstable_by_key(|t| t.thread.id);
+
+	respond(&req, &threads)
+}
+
+/// Thread metainformation
+#[get("/threads/{id}")]
+async fn get_thread(
+	req: HttpRequest,
+	id: web::Path<u64>,
+	registry: web::Data<Addr<Registry>>,
+) -> Result<HttpResponse> {
+	let feed = get_feed(&registry, id.into_inner()).await?;
+	let (send, receive) = oneshot::channel();
+	feed.do_send(ReadMeta(send));
+
+	respond(&req, &receive.await.map_err(ErrorInternalServerError)?)
+}
+
+/// Posts of a thread page
+#[get("/threads/{id}/{page}")]
+async fn get_page(
+	req: HttpRequest,
+	path: web::Path<(u64, u32)>,
+	registry: web::Data<Addr<Registry>>,
+) -> Result<HttpResponse> {
+	let (thread, page) = path.into_inner();
+	let mut posts = read_page(&get_feed(&registry, thread).await?, page)
+		.await?
+		.ok_or_else(|| ErrorNotFound(format!("page not found: {}", page)))?;
+	// The OP is only part of the first page
+	posts.retain(|p| p.page == page);
+
+	respond(
+		&req,
+		&ImmutablePage {
+			thread,
+			page,
+			posts,
+		},
+	)
+}
+
+/// Single post by ID
+#[get("/posts/{id}")]
+async fn get_post(
+	req: HttpRequest,
+	id: web::Path<u64>,
+	registry: web::Data<Addr<Registry>>,
+) -> Result<HttpResponse> {
+	let id = id.into_inner();
+
+	// DB connections are bound to the global Tokio runtime
+	let (thread, page) = mt_context::TOKIO_RUNTIME
+		.spawn(body::lookup_post_location(id))
+		.await
+		.map_err(ErrorInternalServerError)?
+		.map_err(ErrorInternalServerError)?
+		.ok_or_else(|| ErrorNotFound(format!("post not found: {}", id)))?;
+	let post = read_page(&get_feed(&registry, thread).await?, page)
+		.await?
+		.and_then(|posts| posts.into_iter().find(|p| p.id == id))
+		.ok_or_else(|| ErrorNotFound(format!("post not found: {}", id)))?;
+
+	respond(&req, &post)
+}
+
+/// Query string of a search request
+#[derive(Deserialize)]
+struct SearchQuery {
+	q: String,
+	tag: Option<String>,
+	thread: Option<u64>,
+	#[serde(default)]
+	page: u32,
+}
+
+/// Full-text search of closed post bodies
+#[get("/search")]
+async fn get_search(
+	req: HttpRequest,
+	query: web::Query<SearchQuery>,
+) -> Result<HttpResponse> {
+	let query = query.into_inner();
+	let search_req = SearchReq {
+		query: query.q,
+		tag: query.tag,
+		thread: query.thread,
+		page: query.page,
+	};
+	search::validate(&search_req).map_err(ErrorBadRequest)?;
+
+	// Anonymous clients have no public key, so their spam score is tracked by
+	// IP
+	if antispam::increment_ip_spam_score(
+		util::client_ip(&req)?,
+		antispam::Action::Search,
+	) {
+		return Err(ErrorTooManyRequests("search rate limit exceeded"));
+	}
+
+	// DB connections are bound to the global Tokio runtime
+	let res = mt_context::TOKIO_RUNTIME
+		.spawn(search::search(search_req))
+		.await
+		.map_err(ErrorInternalServerError)?
+		.map_err(ErrorInternalServerError)?;
+
+	respond(&req, &res)
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::HashMap;
+
+	#[test]
+	fn sorted_keys() {
+		let val = serde_json::to_value(
+			vec![("b", vec![("d", 1), ("c", 2)]), ("a", vec![])]
+				.into_iter()
+				.map(|(k, v)| (k, v.into_iter().collect::<HashMap<_, _>>()))
+				.collect::<HashMap<_, _>>(),
+		)
+		.unwrap();
+		assert_eq!(
+			serde_json::to_string(&val).unwrap(),
+			r#"{"a":{},"b":{"c":2,"d":1}}"#
+		);
+	}
+}
@@ -0,0 +1,64 @@
+use sha2::{Digest, Sha256};
+use std::{
+	collections::HashMap,
+	path::{Component, Path},
+	sync::RwLock,
+};
+
+/// Directory static assets are served from under /assets
+pub const DIR: &str = "./www";
+
+lazy_static::lazy_static! {
+	/// Content fingerprints of static assets by their path relative to DIR.
+	///
+	/// Static assets only change on deployment, which restarts the server, so
+	/// each asset is hashed only once.
+	static ref FINGERPRINTS: RwLock<HashMap<String, String>> =
+		Default::default();
+}
+
+/// Return the content fingerprint of a static asset by its path relative to
+/// DIR, if the asset exists
+pub fn fingerprint(path: &str) -> Option<String> {
+	if let Some(f) = FINGERPRINTS.read().unwrap().get(path) {
+		return Some(f.clone());
+	}
+
+	// Reject paths escaping DIR
+	if !Path::new(path)
+		.components()
+		.all(|c| matches!(c, Component::Normal(_)))
+	{
+		return None;
+	}
+	let f = format!(
+		"{:x}",
+		Sha256::digest(&std::fs::read(Path::new(DIR).join(path)).ok()?)
+	);
+	FINGERPRINTS
+		.write()
+		.unwrap()
+		.insert(path.to_owned(), f.clone());
+	Some(f)
+}
+
+/// Return the URL of a static asset by its path relative to DIR with the
+/// asset's content fingerprint appended, if the asset exists.
+///
+/// Fingerprinted URLs change along with the asset's content and are cached
+/// immutably by clients.
+pub fn url(path: &str) -> String {
+	match fingerprint(path) {
+		Some(f) => format!("/assets/{}?v={}", path, f),
+		None => format!("/assets/{}", path),
+	}
+}
+
+/// Returns, if the request path and query string match the current
+/// fingerprinted URL of a static asset
+pub fn is_fingerprinted(path: &str, query: &str) -> bool {
+	match (path.strip_prefix("/assets/"), query.strip_prefix("v=")) {
+		(Some(path), Some(v)) => fingerprint(path).as_deref() == Some(v),
+		_ => false,
+	}
+}
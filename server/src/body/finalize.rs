@@ -0,0 +1,142 @@
+use crate::{
+	feeds::{ClosePost, ModeratePost, PostLocation, ThreadFeed},
+	mt_context::MTAddr,
+	util::{self, DynResult},
+};
+use common::payloads::post_body::{Command, Node, PendingNode};
+use std::sync::Arc;
+
+/// Replace any nodes pending finalization with their final values
+#[async_recursion::async_recursion]
+pub async fn finalize_pending(n: &mut Node) -> DynResult {
+	use rand::prelude::*;
+
+	match n {
+		Node::Children(ch) => {
+			for ch in ch.iter_mut() {
+				finalize_pending(ch).await?
+			}
+		}
+		Node::Pending(p) => {
+			*n = match p {
+				PendingNode::Autobahn(h) => {
+					Node::Command(Command::Autobahn(*h))
+				}
+				PendingNode::Countdown(s) => {
+					Node::Command(Command::Countdown {
+						start: util::now(),
+						secs: *s,
+					})
+				}
+				PendingNode::Dice {
+					offset,
+					faces,
+					rolls,
+				} => Node::Command(Command::Dice {
+					offset: *offset,
+					faces: *faces,
+					results: {
+						let mut res = Vec::with_capacity(*rolls as usize);
+
+						let mut rng = thread_rng();
+						for _ in 0..*rolls {
+							res.push(rng.gen::<u16>() % *faces + 1);
+						}
+
+						res
+					},
+				}),
+				PendingNode::EightBall => {
+					// TODO: read eightball choices from thread configs
+					static CHOICES: [&str; 5] = [
+						"Yes",
+						"No",
+						"Maybe",
+						"Anta baka?",
+						"Hell yeah, motherfucker!",
+					];
+
+					Node::Command(Command::EightBall(
+						CHOICES[random::<usize>() % CHOICES.len()].into(),
+					))
+				}
+				PendingNode::Flip => Node::Command(Command::Flip(random())),
+				PendingNode::PostLink(id) => {
+					match super::post_location(*id).await? {
+						Some((thread, page)) => Node::PostLink {
+							id: *id,
+							thread,
+							page,
+						},
+						None => Node::Text(format!(">>{}", id)),
+					}
+				}
+				PendingNode::Pyu => Node::Command(Command::Pyu(
+					crate::db::increment_pcount().await?,
+				)),
+				PendingNode::PCount => Node::Command(Command::PCount(
+					crate::db::get_pcount().await?,
+				)),
+			}
+		}
+		_ => (),
+	};
+	Ok(())
+}
+
+/// Return the hours of the first #autobahn command in a finalized body, if any
+fn find_autobahn(n: &Node) -> Option<u16> {
+	match n {
+		Node::Children(ch) => ch.iter().find_map(find_autobahn),
+		Node::Command(Command::Autobahn(h)) => Some(*h),
+		_ => None,
+	}
+}
+
+/// Finalize the body of a post being closed, persist it and propagate the
+/// closure to the post's feed.
+/// If the body contains an #autobahn command, the post's author is banned in
+/// the same transaction as the closure. Running servers disconnect the
+/// author's live clients on the resulting ban notification.
+///
+/// Returns the finalized body or None, if the post has already been closed.
+pub async fn close_post(
+	feed: &MTAddr<ThreadFeed>,
+	loc: PostLocation,
+	mut body: Node,
+) -> DynResult<Option<Arc<Node>>> {
+	finalize_pending(&mut body).await?;
+
+	let autobahn = find_autobahn(&body)
+		.filter(|h| *h != 0)
+		.map(|hours| (hours as u32 * 60 * 60, format!("#autobahn({})", hours)));
+	let closed = match crate::db::close_post(
+		loc.id,
+		&body,
+		&crate::search::index_text(&body),
+		autobahn
+			.as_ref()
+			.map(|(length, reason)| crate::db::SelfBan {
+				length: *length,
+				reason,
+			}),
+	)
+	.await?
+	{
+		Some(c) => c,
+		None => return Ok(None),
+	};
+
+	let body = Arc::new(body);
+	feed.do_send(ClosePost {
+		loc: loc.clone(),
+		body: body.clone(),
+	});
+	if let Some(p) = closed.self_ban {
+		feed.do_send(ModeratePost {
+			loc,
+			entry: p.entry,
+		});
+	}
+	Ok(Some(body))
+}
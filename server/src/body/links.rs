@@ -83,6 +83,24 @@ pub async fn post_location(id: u64) -> Result<Option<(u64, u32)>, sqlx::Error> {
 	})
 }
 
+/// Fetch post location as (thread, page) from the cache or the DB without
+/// inserting into the cache. Used for client-requested lookups, so that
+/// arbitrary post IDs do not grow the never-evicted cache.
+pub async fn lookup_post_location(
+	id: u64,
+) -> Result<Option<(u64, u32)>, sqlx::Error> {
+	use PostLocation::*;
+
+	if let Some(rec) = read_cache(|c| c.get(&id).cloned()) {
+		match &*rec.read().await {
+			Exists { thread, page } => return Ok(Some((*thread, *page))),
+			DoesNotExist => return Ok(None),
+			NotFetched => (),
+		}
+	}
+	db::get_post_parenthood(id).await
+}
+
 /// Known post location that can be inserted into the cache
 pub struct KnownPostLocation {
 	pub id: u64,
@@ -129,6 +147,32 @@ where
 	write_cache(|c| c.extend(ex));
 }
 
+/// Remove the cached locations of deleted posts
+pub fn forget_post_locations(ids: impl IntoIterator<Item = u64>) {
+	write_cache(|c| {
+		for id in ids {
+			c.remove(&id);
+		}
+	});
+}
+
+/// Remove the cached locations of all posts in deleted threads
+pub fn forget_thread_locations(threads: &[u64]) {
+	use PostLocation::*;
+
+	write_cache(|c| {
+		c.retain(|_, loc| match loc.try_read() {
+			Ok(loc) => match &*loc {
+				Exists { thread, .. } => !threads.contains(thread),
+				_ => true,
+			},
+			// Locked by a concurrent fetch from the DB, which reflects the
+			// deletion, unless it raced with it
+			Err(_) => true,
+		})
+	});
+}
+
 /// Parses a potential post link and return the target post's location
 fn parse_post_link(word: &str, extra_gt: usize) -> Option<(u64, PostLocation)> {
 	word[2 + extra_gt as usize..].parse().ok().map(|id| {
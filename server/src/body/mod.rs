@@ -1,11 +1,16 @@
 mod commands;
+mod finalize;
 mod formatting;
 mod fragment;
 mod links;
 pub mod persist_open;
 mod urls;
 
-pub use links::{cache_locations, post_location, KnownPostLocation};
+pub use finalize::{close_post, finalize_pending};
+pub use links::{
+	cache_locations, forget_post_locations, forget_thread_locations,
+	lookup_post_location, post_location, KnownPostLocation,
+};
 
 use common::payloads::post_body::Node;
 
@@ -26,9 +31,9 @@ const AUTOBAHN_PREFIX: &str = "autobahn";
 ///
 /// All performed on one thread to maximize thread locality.
 /// Yields of work sharing here are doubtable.
-//
-// TODO: finalization on post closure should be done with a separate async
-// traversal function run by the Client
+///
+/// Nodes pending finalization on post closure are finalized separately with
+/// finalize_pending().
 pub fn parse(body: &str, open: bool) -> Node {
 	let mut dst = Node::Empty;
 	if !body.is_empty() {
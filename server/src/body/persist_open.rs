@@ -1,6 +1,7 @@
 use crate::{
+	metrics::{BodyFlusherMetrics, GetMetrics},
 	mt_context::{AsyncHandler, MTContext},
-	util::{self, Pulse},
+	util::{self, Flush, Pulse},
 };
 use actix::prelude::*;
 use async_trait::async_trait;
@@ -12,6 +13,7 @@ use std::{collections::HashMap, sync::Arc};
 pub struct BodyFlusher {
 	bodies: HashMap<u64, Arc<Node>>,
 	pending_pulse: bool,
+	metrics: BodyFlusherMetrics,
 }
 
 impl Actor for BodyFlusher {
@@ -56,8 +58,38 @@ impl AsyncHandler<Pulse> for BodyFlusher {
 		_: &mut <Self as Actor>::Context,
 	) -> Result<(), Self::Error> {
 		self.pending_pulse = false;
+		self.metrics.batch_size.observe(self.bodies.len() as f64);
 		crate::db::write_open_post_bodies(std::mem::take(&mut self.bodies))
 			.await?;
 		Ok(())
 	}
 }
+
+#[async_trait]
+impl AsyncHandler<Flush> for BodyFlusher {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		req: Flush,
+		ctx: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		let res = AsyncHandler::<Pulse>::handle(self, Pulse, ctx).await;
+		req.done();
+		res
+	}
+}
+
+#[async_trait]
+impl AsyncHandler<GetMetrics<BodyFlusherMetrics>> for BodyFlusher {
+	type Error = ();
+
+	async fn handle(
+		&mut self,
+		req: GetMetrics<BodyFlusherMetrics>,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		req.reply(self.metrics.clone());
+		Ok(())
+	}
+}
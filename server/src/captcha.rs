@@ -0,0 +1,225 @@
+use crate::{
+	config,
+	util::{self, DynResult},
+};
+use common::payloads::{CaptchaChallenge, CaptchaImage};
+use rand::prelude::*;
+use std::{
+	path::{Path, PathBuf},
+	time::{Duration, Instant},
+};
+
+/// Number of images in a captcha
+const IMAGE_COUNT: usize = 9;
+
+/// Minimum and maximum number of images matching the captcha tag
+const MATCHING_COUNT: (usize, usize) = (2, 4);
+
+/// Time an issued captcha stays valid for
+const CAPTCHA_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Captcha issued to a client together with its solution
+#[derive(Debug)]
+pub struct Captcha {
+	/// Sorted indices of images matching the tag
+	solution: Vec<u8>,
+
+	/// Time the captcha was issued at
+	issued: Instant,
+}
+
+impl Captcha {
+	/// Generate a new captcha from the local image pool.
+	///
+	/// The images of each tag in Config::captcha_tags are read from a
+	/// subdirectory of Server::captcha_dir named after the tag.
+	pub async fn generate() -> DynResult<(Self, CaptchaChallenge)> {
+		let tags = config::get().captcha_tags.clone();
+		let dir = config::SERVER.captcha_dir.clone();
+
+		util::run_in_rayon(move || -> DynResult<(Self, CaptchaChallenge)> {
+			let mut rng = thread_rng();
+			let pool = tags
+				.iter()
+				.map(|t| list_images(&dir.join(t)))
+				.collect::<Result<Vec<_>, _>>()?;
+
+			let mut order: Vec<usize> = (0..tags.len()).collect();
+			order.shuffle(&mut rng);
+			for i in order {
+				let others: Vec<&PathBuf> = pool
+					.iter()
+					.enumerate()
+					.filter(|(j, _)| *j != i)
+					.flat_map(|(_, p)| p.iter())
+					.collect();
+				let (files, solution) =
+					match compose(&mut rng, &pool[i], &others) {
+						Some(c) => c,
+						None => continue,
+					};
+
+				return Ok((
+					Self {
+						solution,
+						issued: Instant::now(),
+					},
+					CaptchaChallenge {
+						tag: tags[i].clone(),
+						images: files
+							.into_iter()
+							.map(|f| -> DynResult<CaptchaImage> {
+								Ok(CaptchaImage {
+									mime: mime_type(f).into(),
+									data: std::fs::read(f)?,
+								})
+							})
+							.collect::<DynResult<_>>()?,
+					},
+				));
+			}
+
+			Err("captcha image pool too small".into())
+		})
+		.await?
+	}
+
+	/// Check a solution submitted by the client
+	pub fn check(&self, mut selected: Vec<u8>) -> bool {
+		if self.issued.elapsed() > CAPTCHA_TTL {
+			return false;
+		}
+		selected.sort_unstable();
+		selected.dedup();
+		selected == self.solution
+	}
+}
+
+/// Return the MIME type of an image file by its extension
+fn mime_type(path: &Path) -> &'static str {
+	match path
+		.extension()
+		.and_then(|e| e.to_str())
+		.map(|e| e.to_ascii_lowercase())
+		.as_deref()
+	{
+		Some("png") => "image/png",
+		Some("gif") => "image/gif",
+		Some("webp") => "image/webp",
+		_ => "image/jpeg",
+	}
+}
+
+/// List all image files in a captcha pool directory
+fn list_images(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+	let mut out = Vec::new();
+	for e in std::fs::read_dir(dir)? {
+		let path = e?.path();
+		if path.is_file()
+			&& matches!(
+				path.extension()
+					.and_then(|e| e.to_str())
+					.map(|e| e.to_ascii_lowercase())
+					.as_deref(),
+				Some("jpg" | "jpeg" | "png" | "gif" | "webp")
+			) {
+			out.push(path);
+		}
+	}
+	Ok(out)
+}
+
+/// Randomly pick captcha images from the pool of the target tag and the pool
+/// of all other tags.
+///
+/// Returns the images in display order and the sorted indices of images
+/// matching the target tag or None, if the pools are too small.
+fn compose<'a, R: Rng>(
+	rng: &mut R,
+	target: &'a [PathBuf],
+	others: &[&'a PathBuf],
+) -> Option<(Vec<&'a PathBuf>, Vec<u8>)> {
+	let matching = std::cmp::min(
+		rng.gen_range(MATCHING_COUNT.0..=MATCHING_COUNT.1),
+		target.len(),
+	);
+	if matching < MATCHING_COUNT.0 || others.len() < IMAGE_COUNT - matching {
+		return None;
+	}
+
+	let mut picked: Vec<(&PathBuf, bool)> = target
+		.choose_multiple(rng, matching)
+		.map(|p| (p, true))
+		.chain(
+			others
+				.choose_multiple(rng, IMAGE_COUNT - matching)
+				.map(|p| (*p, false)),
+		)
+		.collect();
+	picked.shuffle(rng);
+
+	let solution = picked
+		.iter()
+		.enumerate()
+		.filter(|(_, (_, m))| *m)
+		.map(|(i, _)| i as u8)
+		.collect();
+	Some((picked.into_iter().map(|(p, _)| p).collect(), solution))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn paths(prefix: &str, n: usize) -> Vec<PathBuf> {
+		(0..n)
+			.map(|i| format!("{}/{}.jpg", prefix, i).into())
+			.collect()
+	}
+
+	#[test]
+	fn compose_captcha() {
+		let target = paths("cirno", 10);
+		let others = paths("hakurei_reimu", 10);
+		let others: Vec<&PathBuf> = others.iter().collect();
+
+		for _ in 0..100 {
+			let (files, solution) =
+				compose(&mut thread_rng(), &target, &others).unwrap();
+			assert_eq!(files.len(), IMAGE_COUNT);
+			assert!(solution.len() >= MATCHING_COUNT.0);
+			assert!(solution.len() <= MATCHING_COUNT.1);
+			for (i, f) in files.iter().enumerate() {
+				assert_eq!(
+					solution.contains(&(i as u8)),
+					f.starts_with("cirno")
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn compose_from_too_small_pool() {
+		let target = paths("cirno", 1);
+		let others = paths("hakurei_reimu", 10);
+		let others: Vec<&PathBuf> = others.iter().collect();
+		assert!(compose(&mut thread_rng(), &target, &others).is_none());
+
+		let target = paths("cirno", 10);
+		let others = paths("hakurei_reimu", 4);
+		let others: Vec<&PathBuf> = others.iter().collect();
+		assert!(compose(&mut thread_rng(), &target, &others).is_none());
+	}
+
+	#[test]
+	fn check_solution() {
+		let c = Captcha {
+			solution: vec![1, 4, 7],
+			issued: Instant::now(),
+		};
+		assert!(c.check(vec![7, 1, 4]));
+		assert!(c.check(vec![1, 4, 4, 7]));
+		assert!(!c.check(vec![1, 4]));
+		assert!(!c.check(vec![1, 4, 7, 8]));
+	}
+}
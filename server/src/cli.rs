@@ -0,0 +1,167 @@
+use crate::{
+	config::{BanCommand, Command, ConfigCommand, StaffCommand, ThreadCommand},
+	db, search,
+	util::DynResult,
+};
+use std::io::Read;
+
+/// Execute an administrative command against the database and exit.
+/// Must be called after the database connection has been opened.
+pub async fn run(cmd: &Command) -> DynResult {
+	match cmd {
+		// Handled by the caller
+		Command::Serve => (),
+
+		// Migrations are applied on opening the database
+		Command::Migrate => println!("migrations applied"),
+		Command::Config(cmd) => run_config(cmd).await?,
+		Command::Staff(cmd) => run_staff(cmd).await?,
+		Command::Thread(cmd) => run_thread(cmd).await?,
+		Command::Ban(cmd) => run_ban(cmd).await?,
+		Command::IndexSearch => index_search().await?,
+	};
+	Ok(())
+}
+
+async fn run_config(cmd: &ConfigCommand) -> DynResult {
+	match cmd {
+		ConfigCommand::Get => {
+			println!(
+				"{}",
+				serde_json::to_string_pretty(
+					&db::get_config().await?.unwrap_or_default()
+				)?
+			);
+		}
+		ConfigCommand::Set { path } => {
+			let mut buf = Vec::new();
+			match path {
+				Some(path) => buf = std::fs::read(path)?,
+				None => {
+					std::io::stdin().read_to_end(&mut buf)?;
+				}
+			};
+			db::write_config(&serde_json::from_slice(&buf)?).await?;
+			println!("configurations updated");
+		}
+	};
+	Ok(())
+}
+
+async fn run_staff(cmd: &StaffCommand) -> DynResult {
+	match cmd {
+		StaffCommand::Add {
+			public_key,
+			level,
+			title,
+		} => {
+			db::set_staff(*public_key, (*level).into(), title.as_deref())
+				.await?;
+			println!("staff account of public key {} set", public_key);
+		}
+		StaffCommand::Remove { public_key } => {
+			if !db::remove_staff(*public_key).await? {
+				return Err(format!(
+					"public key {} has no staff account",
+					public_key
+				)
+				.into());
+			}
+			println!("staff account of public key {} removed", public_key);
+		}
+	};
+	Ok(())
+}
+
+async fn run_thread(cmd: &ThreadCommand) -> DynResult {
+	match cmd {
+		// Running servers are notified of the deletion by the DB
+		ThreadCommand::Delete { id } => {
+			if !db::delete_thread(*id).await? {
+				return Err(format!("thread {} does not exist", id).into());
+			}
+			println!("thread {} deleted", id);
+		}
+		// Running servers are notified of the change by the DB
+		ThreadCommand::Lock { id, unlock } => {
+			if !db::set_thread_locked(*id, !unlock).await? {
+				return Err(format!("thread {} does not exist", id).into());
+			}
+			println!(
+				"thread {} {}",
+				id,
+				if *unlock { "unlocked" } else { "locked" }
+			);
+		}
+	};
+	Ok(())
+}
+
+async fn run_ban(cmd: &BanCommand) -> DynResult {
+	match cmd {
+		// Running servers disconnect any banned clients on notification by
+		// the DB
+		BanCommand::Add {
+			public_key,
+			ip,
+			hours,
+			reason,
+		} => {
+			let id = db::insert_ban(
+				*public_key,
+				ip.as_deref(),
+				*hours as u64 * 3600,
+				reason,
+			)
+			.await?;
+			println!("ban {} added", id);
+		}
+		BanCommand::List => {
+			for b in db::get_bans().await? {
+				let target = match (b.public_key, b.ip) {
+					(Some(k), _) => format!("public_key={}", k),
+					(None, Some(ip)) => format!("ip={}", ip),
+					(None, None) => String::new(),
+				};
+				let staff = b
+					.staff
+					.map(|s| s.to_string())
+					.unwrap_or_else(|| "-".into());
+				println!(
+					"id={}\t{}\tstaff={}\texpires={}\treason={:?}",
+					b.id, target, staff, b.expires, b.reason
+				);
+			}
+		}
+		BanCommand::Remove { id } => {
+			if !db::delete_ban(*id).await? {
+				return Err(format!("ban {} does not exist", id).into());
+			}
+			println!("ban {} removed", id);
+		}
+	};
+	Ok(())
+}
+
+/// Index closed posts without a full-text search index in batches
+async fn index_search() -> DynResult {
+	let mut after = 0;
+	let mut indexed = 0;
+	loop {
+		let posts = db::get_unindexed_posts(after, 1000).await?;
+		after = match posts.last() {
+			Some((id, _)) => *id,
+			None => break,
+		};
+		indexed += posts.len();
+		db::set_search_text(
+			&posts
+				.iter()
+				.map(|(id, body)| (*id, search::index_text(body)))
+				.collect::<Vec<_>>(),
+		)
+		.await?;
+	}
+	println!("{} posts indexed", indexed);
+	Ok(())
+}
@@ -1,5 +1,7 @@
 use super::{
-	message_handler::{HandleMessage, MessageHandler, MessageResult},
+	message_handler::{
+		ClearOpenPost, HandleMessage, MessageHandler, MessageResult,
+	},
 	str_err,
 };
 use crate::{
@@ -11,6 +13,13 @@ use crate::{
 };
 use actix::prelude::*;
 use actix_web_actors::ws;
+use common::{
+	payloads::{
+		moderation::BanNotice,
+		post_body::{Node, PostBody},
+	},
+	Encoder, MessageType,
+};
 use std::{net::IpAddr, sync::Arc};
 
 /// Client instance controller
@@ -21,16 +30,25 @@ pub struct Client {
 
 	/// Actor handling messages on the tokio multithreaded runtime
 	message_handler: Option<MTAddr<MessageHandler>>,
+
+	/// Ban of the client's IP found on connection, if any
+	ban: Option<BanNotice>,
 }
 
 impl Actor for Client {
 	type Context = ws::WebsocketContext<Self>;
 
 	fn started(&mut self, ctx: &mut Self::Context) {
+		if let Some(ban) = self.ban.take() {
+			self.send_ban(ctx, &ban);
+			return;
+		}
+
 		let ref s: super::State = *self.state;
 		s.registry
 			.send(registry::RegisterClient {
 				id: s.id,
+				ip: s.ip,
 				addr: ctx.address(),
 			})
 			.into_actor(self)
@@ -135,6 +153,87 @@ impl Handler<Disconnect> for Client {
 	}
 }
 
+/// Notify client of a ban affecting it and disconnect it
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Ban(pub BanNotice);
+
+impl Handler<Ban> for Client {
+	type Result = ();
+
+	fn handle(&mut self, msg: Ban, ctx: &mut Self::Context) -> Self::Result {
+		self.send_ban(ctx, &msg.0);
+	}
+}
+
+/// Notify client of a server shutdown and disconnect it. Contains the number of
+/// seconds the client should wait before reconnecting.
+/// Resolves, once the client's message handler has processed all messages
+/// received before and stopped.
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct Restart(pub u32);
+
+impl Handler<Restart> for Client {
+	type Result = ResponseFuture<()>;
+
+	fn handle(
+		&mut self,
+		msg: Restart,
+		ctx: &mut Self::Context,
+	) -> Self::Result {
+		match Encoder::encode(MessageType::ServerRestarting, &msg.0) {
+			Ok(buf) => {
+				ctx.binary(buf);
+				ctx.close(Some(ws::CloseReason {
+					code: ws::CloseCode::Restart,
+					description: Some("server restarting".into()),
+				}));
+				ctx.stop();
+			}
+			Err(e) => self.fail(ctx, &e.into()),
+		}
+
+		let stopped = self.message_handler.take().map(|h| {
+			let (send, receive) = tokio::sync::oneshot::channel();
+			h.do_send(util::Stop(send));
+			receive
+		});
+		Box::pin(async move {
+			if let Some(receive) = stopped {
+				// Stopped handlers drop the sender and have nothing to process
+				std::mem::drop(receive.await);
+			}
+		})
+	}
+}
+
+/// Notify the client its open post was closed by the server
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct OpenPostClosed {
+	pub id: u64,
+	pub body: Arc<Node>,
+}
+
+impl Handler<OpenPostClosed> for Client {
+	type Result = ();
+
+	fn handle(
+		&mut self,
+		OpenPostClosed { id, body }: OpenPostClosed,
+		ctx: &mut Self::Context,
+	) -> Self::Result {
+		if let Some(h) = &self.message_handler {
+			h.do_send(ClearOpenPost(id));
+		}
+		match Encoder::encode(MessageType::ClosePost, &PostBody { id, body }) {
+			Ok(buf) => ctx.binary(buf),
+			Err(e) => self.fail(ctx, &e.into()),
+		}
+	}
+}
+
 /// Send message to client
 #[derive(Message, Clone)]
 #[rtype(result = "()")]
@@ -181,11 +280,15 @@ impl Handler<SendMessageBatch> for Client {
 }
 
 impl Client {
-	/// Create fresh unconnected client
+	/// Create fresh unconnected client.
+	///
+	/// If ban is set, the client is sent the ban notice and disconnected right
+	/// after connecting.
 	pub fn new(
 		ip: IpAddr,
 		registry: Addr<Registry>,
 		index_feed: MTAddr<IndexFeed>,
+		ban: Option<BanNotice>,
 	) -> Self {
 		lazy_static::lazy_static! {
 			static ref ID_GEN: util::IDGenerator = Default::default();
@@ -199,6 +302,7 @@ impl Client {
 				id: ID_GEN.next(),
 			}),
 			message_handler: None,
+			ban,
 		}
 	}
 
@@ -217,4 +321,20 @@ impl Client {
 		// message?
 		ctx.stop();
 	}
+
+	/// Send the client a ban notice and stop the Actor
+	#[cold]
+	fn send_ban(&self, ctx: &mut <Self as Actor>::Context, ban: &BanNotice) {
+		log::info!("disconnecting banned client {}: {:?}", self.state.ip, ban);
+
+		match Encoder::encode(MessageType::Banned, ban) {
+			Ok(buf) => ctx.binary(buf),
+			Err(e) => return self.fail(ctx, &e.into()),
+		}
+		ctx.close(Some(ws::CloseReason {
+			code: ws::CloseCode::Policy,
+			description: Some("banned".into()),
+		}));
+		ctx.stop();
+	}
 }
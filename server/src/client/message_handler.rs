@@ -1,11 +1,12 @@
 use super::{client::Client, str_err};
 use crate::{
+	antispam,
 	body::{cache_locations, KnownPostLocation},
-	config, db,
-	feeds::{self, AnyFeed, PostLocation, ThreadFeed},
+	captcha, config, db,
+	feeds::{self, AnyFeed, ThreadFeed},
 	message::Message,
 	mt_context::{AsyncHandler, MTAddr, MTContext},
-	registry,
+	registry, search,
 	util::{self, DynResult},
 };
 use actix::{Actor, Addr};
@@ -13,8 +14,13 @@ use actix_web::web::Bytes;
 use async_trait::async_trait;
 use common::{
 	payloads::{
-		self, post_body::TextPatch, Authorization, HandshakeReq,
-		PostCreationReq, Signature, ThreadCreationReq,
+		self,
+		moderation::{
+			BanNotice, ModerationAction, ModerationLevel, PostModeration,
+		},
+		post_body::TextPatch,
+		Authorization, HandshakeReq, PostCreationReq, SearchReq, Signature,
+		ThreadCreationReq,
 	},
 	Decoder, Encoder, MessageType,
 };
@@ -83,8 +89,19 @@ struct OpenPost {
 	feed: MTAddr<ThreadFeed>,
 }
 
-// TODO: to protect against replay again attacks, the nonce for the handshake
-// has to be supplied by the server to verify a already registered public key
+/// Time a handshake challenge nonce issued by the server stays valid for
+const CHALLENGE_TTL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Time a proof of work challenge issued by the server stays valid for
+const PROOF_OF_WORK_TTL: std::time::Duration =
+	std::time::Duration::from_secs(10 * 60);
+
+/// Proof of work challenge issued to the client
+#[derive(Debug)]
+struct IssuedProofOfWork {
+	challenge: payloads::ProofOfWorkChallenge,
+	issued: std::time::Instant,
+}
 
 /// Client connection state
 #[derive(Debug)]
@@ -95,12 +112,22 @@ enum ConnState {
 	/// Sent handshake message and it was accepted
 	AcceptedHandshake,
 
-	/// Public key already registered. Requested client to send a HandshakeReq
-	/// with Authorization::Saved.
-	RequestedReshake { pub_key: Vec<u8> },
+	/// Public key already registered. Sent the client a nonce to sign and
+	/// return in a HandshakeReq with Authorization::SignedChallenge.
+	///
+	/// The nonce is consumed on the first response to it.
+	Challenged {
+		pub_key: Vec<u8>,
+		nonce: [u8; 32],
+		issued: std::time::Instant,
+	},
 
 	/// Client synchronized to a feed
 	Synchronized { id: u64, feed: AnyFeed },
+
+	/// Client is banned and is being disconnected. Any further messages are
+	/// ignored.
+	Banned,
 }
 
 /// Handles incoming messages asynchronously\
@@ -118,6 +145,12 @@ pub struct MessageHandler {
 	/// Public key public and private ID set
 	pub_key: PubKeyDesc,
 
+	/// Captcha issued to the client and not yet solved
+	captcha: Option<captcha::Captcha>,
+
+	/// Proof of work challenge issued to the client and not yet solved
+	proof_of_work: Option<IssuedProofOfWork>,
+
 	/// Calling Client address
 	client: Addr<Client>,
 
@@ -170,6 +203,8 @@ impl MessageHandler {
 			client,
 			conn_state: ConnState::Connected,
 			open_post: None,
+			captcha: None,
+			proof_of_work: None,
 			message: None,
 			pub_key: Default::default(),
 		}
@@ -216,21 +251,19 @@ impl MessageHandler {
 
 					first = false;
 					match &self.conn_state {
-						Connected => {
+						Connected | Challenged { .. } => {
 							expect!(Handshake);
 							self.handle_handshake(&mut dec).await?;
-							self.send(CurrentTime, &util::now())?;
-							self.send(Configs, &config::get().public)?;
-						}
-						RequestedReshake { pub_key } => {
-							expect!(Handshake);
-							let pk = pub_key.clone();
-							self.handle_reshake(&mut dec, &pk)?;
+							if let AcceptedHandshake = self.conn_state {
+								self.send(CurrentTime, &util::now())?;
+								self.send(Configs, &config::get().public)?;
+							}
 						}
 						AcceptedHandshake | Synchronized { .. } => {
 							self.handle_message_after_handshake(t, &mut dec)
 								.await?;
 						}
+						Banned => return Ok(()),
 					}
 				}
 			}
@@ -261,6 +294,7 @@ impl MessageHandler {
 		match t {
 			InsertThread => self.insert_thread(decode!()).await,
 			Synchronize => self.synchronize(decode!()).await,
+			SynchronizeToPost => self.synchronize_to_post(decode!()).await,
 			InsertPost => self.insert_post(decode!()).await,
 			Append => {
 				let ch = decode!();
@@ -277,7 +311,8 @@ impl MessageHandler {
 				})
 			}
 			PatchPostBody => self.patch_body(decode!()),
-			Page => self.fetch_page(decode!()),
+			Page => self.fetch_page(decode!()).await,
+			Post => self.fetch_post(decode!()).await,
 			UsedTags => {
 				skip_payload!();
 				self.state
@@ -289,6 +324,12 @@ impl MessageHandler {
 				skip_payload!();
 				self.close_post().await
 			}
+			ReclaimOpenPost => self.reclaim_open_post(decode!()).await,
+			Captcha => self.solve_captcha(decode!()).await,
+			ProofOfWork => self.solve_proof_of_work(decode!()).await,
+			Moderate => self.moderate(decode!()).await,
+			SetServerConfig => self.set_server_config(decode!()).await,
+			Search => self.search(decode!()).await,
 			_ => str_err!("unhandled message type: {:?}", t),
 		}
 	}
@@ -318,17 +359,57 @@ impl MessageHandler {
 				})
 				.await??,
 		};
-
-		// TODO: attempt to reclaim an open post lost to disconnection, if any
-		//  specified by client
-
 		Ok(())
 	}
 
-	/// Fetch a page from a currently synced to feed
-	fn fetch_page(&mut self, page: i32) -> DynResult {
+	/// Synchronize to the thread containing a post and fetch the post's page
+	#[cold]
+	async fn synchronize_to_post(&mut self, id: u64) -> DynResult {
+		let mut location = crate::body::lookup_post_location(id).await?;
+		if let Some((thread, _)) = location {
+			// The thread can be deleted after the location was cached
+			if self.find_thread_feed(thread).await?.is_none() {
+				location = None;
+			}
+		}
+
+		// Send immediately instead of buffering, so the location arrives
+		// before any messages from the thread feed
+		let res = payloads::SynchronizeToPostRes { id, location };
+		log::debug!("<<< {:?}: {:?}", MessageType::SynchronizeToPost, res);
+		self.client
+			.do_send(super::SendMessage(Message::new(Encoder::encode(
+				MessageType::SynchronizeToPost,
+				&res,
+			)?)));
+
+		let (thread, page) = match location {
+			Some(loc) => loc,
+			None => return Ok(()),
+		};
+
 		match &self.conn_state {
-			ConnState::Synchronized { feed, .. } => match feed {
+			// The registry does not resend the thread metainformation, if
+			// already synchronized to the thread
+			ConnState::Synchronized {
+				id,
+				feed: AnyFeed::Thread(f),
+			} if id == &thread => {
+				f.do_send(feeds::FetchFeedData(self.client.clone()));
+			}
+			_ => self.synchronize(thread).await?,
+		};
+		self.fetch_page(page as i32).await
+	}
+
+	/// Fetch a page from a currently synced to feed.
+	///
+	/// Shadow binned posts are omitted from pages sent by feeds. Any on the
+	/// page are sent separately, if authored by the client or the client is
+	/// staff.
+	async fn fetch_page(&mut self, page: i32) -> DynResult {
+		let thread = match &self.conn_state {
+			ConnState::Synchronized { id, feed } => match feed {
 				AnyFeed::Index(_) => {
 					str_err!("can not fetch pages on index feed")
 				}
@@ -337,22 +418,132 @@ impl MessageHandler {
 						id: page,
 						client: self.client.clone(),
 					});
-					Ok(())
+					*id
 				}
 			},
 			_ => {
 				str_err!("need to be synchronized to a thread to request pages")
 			}
+		};
+
+		// Staff accounts can change during the connection
+		let author = match db::get_staff(self.pub_key.priv_id).await? {
+			Some(_) => None,
+			None => Some(self.pub_key.priv_id),
+		};
+		for p in db::get_shadow_binned_posts(thread, page, author).await? {
+			self.send(MessageType::Post, &p)?;
 		}
+		Ok(())
 	}
 
-	/// Validates a solved captcha
-	pub fn check_captcha(&mut self, solution: &[u8]) -> DynResult {
-		if config::get().public.enable_antispam {
-			check_len!(solution, 4);
+	/// Send a single post from any thread to the client, such as for link
+	/// previews. Missing and shadow binned posts are ignored.
+	async fn fetch_post(&mut self, id: u64) -> DynResult {
+		let (thread, page) = match crate::body::lookup_post_location(id).await?
+		{
+			Some(loc) => loc,
+			None => return Ok(()),
+		};
 
-			// TODO: Use pub key for spam detection bans
-			// TODO: validate solution
+		// The thread can be deleted after the location was cached
+		let feed = match self.find_thread_feed(thread).await? {
+			Some(f) => f,
+			None => return Ok(()),
+		};
+		let (send, receive) = tokio::sync::oneshot::channel();
+		feed.do_send(feeds::ReadPost {
+			loc: feeds::PostLocation { id, page },
+			reply: send,
+		});
+		match receive.await? {
+			Some(p) if !p.shadow_binned => {
+				self.send(MessageType::Post, &p)?;
+			}
+			_ => (),
+		}
+		Ok(())
+	}
+
+	/// If the client needs to solve a captcha before creating a post, notify
+	/// it and send a captcha or proof of work challenge, depending on the
+	/// configured AntispamMode.
+	///
+	/// Returns, if a captcha was requested.
+	async fn request_captcha(&mut self) -> DynResult<bool> {
+		use common::config::AntispamMode;
+
+		if !antispam::need_captcha(self.pub_key.priv_id).await? {
+			return Ok(false);
+		}
+		self.send(MessageType::NeedCaptcha, &())?;
+		match config::get().public.antispam_mode {
+			AntispamMode::Captcha => self.issue_captcha().await?,
+			AntispamMode::ProofOfWork => self.issue_proof_of_work().await?,
+		}
+		Ok(true)
+	}
+
+	/// Generate a new proof of work challenge scaled to the client's spam
+	/// score and send it to the client
+	async fn issue_proof_of_work(&mut self) -> DynResult {
+		let challenge = payloads::ProofOfWorkChallenge {
+			salt: rand::random(),
+			difficulty: antispam::proof_of_work_difficulty(
+				self.pub_key.priv_id,
+			)
+			.await?,
+		};
+		self.send(MessageType::ProofOfWork, &challenge)?;
+		self.proof_of_work = Some(IssuedProofOfWork {
+			challenge,
+			issued: std::time::Instant::now(),
+		});
+		Ok(())
+	}
+
+	/// Validate a proof of work solution submitted by the client
+	async fn solve_proof_of_work(&mut self, nonce: u64) -> DynResult {
+		let p = self
+			.proof_of_work
+			.take()
+			.ok_or("no proof of work challenge issued")?;
+		if p.issued.elapsed() > PROOF_OF_WORK_TTL {
+			self.send(MessageType::Captcha, &payloads::CaptchaRes::Expired)?;
+			return Ok(());
+		}
+		if !p.challenge.check(nonce) {
+			str_err!("invalid proof of work solution");
+		}
+
+		antispam::record_valid_captcha(self.pub_key.priv_id).await?;
+		self.send(MessageType::Captcha, &payloads::CaptchaRes::Solved)?;
+		Ok(())
+	}
+
+	/// Generate a new captcha and send it to the client
+	async fn issue_captcha(&mut self) -> DynResult {
+		let (c, challenge) = captcha::Captcha::generate().await?;
+		self.send(
+			MessageType::Captcha,
+			&payloads::CaptchaRes::Challenge(challenge),
+		)?;
+		self.captcha = Some(c);
+		Ok(())
+	}
+
+	/// Validate a captcha solution submitted by the client
+	async fn solve_captcha(&mut self, solution: Vec<u8>) -> DynResult {
+		use payloads::CaptchaRes;
+
+		check_len!(solution, 9);
+		let c = self.captcha.take().ok_or("no captcha issued")?;
+		if c.check(solution) {
+			antispam::record_valid_captcha(self.pub_key.priv_id).await?;
+			self.send(MessageType::Captcha, &CaptchaRes::Solved)?;
+		} else {
+			self.send(MessageType::Captcha, &CaptchaRes::Invalid)?;
+			self.issue_captcha().await?;
 		}
 		Ok(())
 	}
@@ -401,7 +592,13 @@ impl MessageHandler {
 		req.tags.sort();
 
 		let [name, trip] = Self::parse_name(req.opts.name)?;
-		self.check_captcha(&req.captcha_solution)?;
+		if self.check_ban().await? || self.request_captcha().await? {
+			return Ok(());
+		}
+		antispam::increment_spam_score(
+			self.pub_key.priv_id,
+			antispam::Action::PostCreation,
+		);
 		let id = db::insert_thread(&mut db::ThreadInsertParams {
 			subject: &req.subject,
 			tags: &mut req.tags,
@@ -411,6 +608,7 @@ impl MessageHandler {
 				trip: trip.as_ref().map(AsRef::as_ref),
 				flag: None, // TODO
 				body: &common::payloads::post_body::Node::Empty,
+				ip: self.state.ip,
 			},
 		})
 		.await?;
@@ -437,7 +635,7 @@ impl MessageHandler {
 			}))
 			.await?;
 		self.send(MessageType::InsertThreadAck, &id)?;
-		self.open_post = Some(OpenPost {
+		self.set_open_post(OpenPost {
 			loc: feeds::PostLocation { id, page: 0 },
 			thread: id,
 			body: Default::default(),
@@ -451,13 +649,23 @@ impl MessageHandler {
 	async fn insert_post(&mut self, req: PostCreationReq) -> DynResult {
 		self.assert_no_open_post()?;
 
-		// TODO: captcha checks
-		// if bindings::need_captcha(self.pub_key.priv_id)? {
-		// 	self.send(MessageType::NeedCaptcha, &())?;
-		// 	return Ok(());
-		// }
+		let flags = match db::get_thread_flags(req.thread).await? {
+			Some(f) => f,
+			None => str_err!("thread not found: {}", req.thread),
+		};
+		if flags.locked {
+			str_err!("thread locked: {}", req.thread);
+		}
+
+		if self.check_ban().await? || self.request_captcha().await? {
+			return Ok(());
+		}
 
 		let [name, trip] = Self::parse_name(req.opts.name)?;
+		antispam::increment_spam_score(
+			self.pub_key.priv_id,
+			antispam::Action::PostCreation,
+		);
 		let (id, page) = db::insert_post(
 			req.thread,
 			req.sage,
@@ -467,6 +675,7 @@ impl MessageHandler {
 				trip: trip.as_ref().map(AsRef::as_ref),
 				flag: None, // TODO
 				body: &common::payloads::post_body::Node::Empty,
+				ip: self.state.ip,
 			},
 		)
 		.await?;
@@ -478,19 +687,7 @@ impl MessageHandler {
 			page,
 		}));
 
-		// Don't fetch feed address, if open post in same feed as synced
-		let feed = match &self.conn_state {
-			ConnState::Synchronized {
-				id,
-				feed: feeds::AnyFeed::Thread(f),
-			} if id == &req.thread => f.clone(),
-			_ => {
-				self.state
-					.registry
-					.send(registry::GetFeed(req.thread))
-					.await??
-			}
-		};
+		let feed = self.get_thread_feed(req.thread).await?;
 
 		// At least try to make it arrive before the post insert
 		self.send(MessageType::InsertPostAck, &id)?;
@@ -508,8 +705,19 @@ impl MessageHandler {
 				},
 			},
 		});
+		if flags.cyclic {
+			let pruned =
+				db::prune_thread(req.thread, config::get().cyclic_thread_limit)
+					.await?;
+			feed.do_send(feeds::PrunePosts(
+				pruned
+					.into_iter()
+					.map(|(id, page)| feeds::PostLocation { id, page })
+					.collect(),
+			));
+		}
 
-		self.open_post = Some(OpenPost {
+		self.set_open_post(OpenPost {
 			loc: feeds::PostLocation { id, page },
 			thread: req.thread,
 			body: Default::default(),
@@ -519,6 +727,121 @@ impl MessageHandler {
 		Ok(())
 	}
 
+	/// Return the address of a thread's feed
+	async fn get_thread_feed(
+		&self,
+		thread: u64,
+	) -> DynResult<MTAddr<ThreadFeed>> {
+		// Don't fetch feed address, if open post in same feed as synced
+		Ok(match &self.conn_state {
+			ConnState::Synchronized {
+				id,
+				feed: feeds::AnyFeed::Thread(f),
+			} if id == &thread => f.clone(),
+			_ => {
+				self.state
+					.registry
+					.send(registry::GetFeed(thread))
+					.await??
+			}
+		})
+	}
+
+	/// Like get_thread_feed, but returns None, if the thread does not exist
+	async fn find_thread_feed(
+		&self,
+		thread: u64,
+	) -> DynResult<Option<MTAddr<ThreadFeed>>> {
+		Ok(match &self.conn_state {
+			ConnState::Synchronized {
+				id,
+				feed: feeds::AnyFeed::Thread(f),
+			} if id == &thread => Some(f.clone()),
+			_ => self
+				.state
+				.registry
+				.send(registry::GetFeed(thread))
+				.await?
+				.ok(),
+		})
+	}
+
+	/// Reclaim an open post created with the same public key and lost to a
+	/// disconnection.
+	///
+	/// The client's body is authoritative and replaces the feed's current
+	/// copy. The feed diffs the two on its next pulse and only propagates the
+	/// difference to other clients.
+	///
+	/// Posts still being edited by another connected client can not be
+	/// reclaimed.
+	async fn reclaim_open_post(
+		&mut self,
+		req: payloads::ReclaimOpenPostReq,
+	) -> DynResult {
+		use payloads::ReclaimOpenPostRes::*;
+
+		self.assert_no_open_post()?;
+
+		let body: Vec<char> = req.body.chars().collect();
+		if body.len() > 2000 {
+			str_err!("body length exceeds bounds")
+		}
+
+		let res = match db::get_post_ownership(req.id).await? {
+			Some(p) if p.public_key == Some(self.pub_key.priv_id) => {
+				if !p.open {
+					AlreadyClosed
+				} else if !self
+					.state
+					.registry
+					.send(registry::SetOpenPost {
+						client: self.state.id,
+						post: Some(req.id),
+					})
+					.await?
+				{
+					InUse
+				} else {
+					let feed = self.get_thread_feed(p.thread).await?;
+					let loc = feeds::PostLocation {
+						id: req.id,
+						page: p.page,
+					};
+					feed.do_send(feeds::SetBody {
+						loc: loc.clone(),
+						body: body.clone(),
+					});
+					antispam::increment_spam_score(
+						self.pub_key.priv_id,
+						antispam::Action::Characters(body.len()),
+					);
+					self.open_post = Some(OpenPost {
+						loc,
+						thread: p.thread,
+						body,
+						feed,
+					});
+					Reclaimed
+				}
+			}
+			_ => NotFound,
+		};
+		self.send(MessageType::ReclaimOpenPost, &res)?;
+
+		Ok(())
+	}
+
+	/// Set the post the client is editing and register it as owned by this
+	/// client
+	fn set_open_post(&mut self, p: OpenPost) {
+		self.state.registry.do_send(registry::SetOpenPost {
+			client: self.state.id,
+			post: Some(p.loc.id),
+		});
+		self.open_post = Some(p);
+	}
+
 	/// Apply diff to text body
 	fn patch_body(&mut self, req: TextPatch) -> DynResult {
 		if req.insert.len() > 2000 {
@@ -571,11 +894,10 @@ impl MessageHandler {
 					loc: p.loc.clone(),
 					body: p.body.clone(),
 				});
-				// TODO: port spam scores to Rust
-				// bindings::increment_spam_score(
-				// 	self.pub_key.priv_id,
-				// 	affected * crate::config::read(|c| c.spam_scores.character),
-				// );
+				antispam::increment_spam_score(
+					self.pub_key.priv_id,
+					antispam::Action::Characters(affected),
+				);
 
 				Ok(())
 			}
@@ -592,57 +914,33 @@ impl MessageHandler {
 		Ok(req)
 	}
 
+	/// Handle a handshake request in either the Connected or Challenged state
 	async fn handle_handshake(&mut self, dec: &mut Decoder) -> DynResult {
 		use common::payloads::{HandshakeRes, PubKeyStatus};
 
+		// Any issued challenge is consumed by the next handshake message, so
+		// that a nonce can never be used twice
+		let prev =
+			std::mem::replace(&mut self.conn_state, ConnState::Connected);
+
 		match Self::decode_handshake(dec)?.auth {
 			Authorization::NewPubKey(pub_key) => {
 				check_len!(pub_key, 1 << 10);
 				let (priv_id, pub_id, fresh) =
 					db::register_public_key(&pub_key).await?;
 
-				self.pub_key = PubKeyDesc {
-					priv_id,
-					pub_id: pub_id.clone(),
-				};
+				self.pub_key = PubKeyDesc { priv_id, pub_id };
 				if fresh {
-					self.state
-						.registry
-						.send(registry::SetPublicKey {
-							client: self.state.id,
-							pub_key: priv_id,
-						})
-						.await??;
-					self.conn_state = ConnState::AcceptedHandshake;
+					self.accept_handshake().await?;
 				} else {
-					self.conn_state = ConnState::RequestedReshake { pub_key };
+					self.issue_challenge(pub_key)?;
 				}
-
-				self.send(
-					MessageType::Handshake,
-					&HandshakeRes {
-						id: pub_id,
-						status: if fresh {
-							PubKeyStatus::Accepted
-						} else {
-							PubKeyStatus::NeedResend
-						},
-					},
-				)?;
 			}
-			Authorization::Saved {
-				id: pub_id,
-				nonce,
-				signature,
-			} => {
+			Authorization::Saved(pub_id) => {
 				match db::get_public_key(&pub_id).await? {
 					Some((priv_id, pub_key)) => {
 						self.pub_key = PubKeyDesc { priv_id, pub_id };
-						self.handle_auth_saved(
-							nonce,
-							signature,
-							pub_key.as_ref(),
-						)?;
+						self.issue_challenge(pub_key)?;
 					}
 					None => {
 						self.send(
@@ -655,19 +953,62 @@ impl MessageHandler {
 					}
 				};
 			}
+			Authorization::SignedChallenge {
+				id: pub_id,
+				signature,
+			} => match prev {
+				ConnState::Challenged {
+					pub_key,
+					nonce,
+					issued,
+				} => {
+					if pub_id != self.pub_key.pub_id {
+						str_err!("different public key public id in challenge response");
+					}
+					if issued.elapsed() > CHALLENGE_TTL {
+						str_err!("handshake challenge expired");
+					}
+					Self::verify_challenge(
+						&pub_id, &nonce, &signature, &pub_key,
+					)?;
+					self.accept_handshake().await?;
+				}
+				// Also covers replays of an already consumed nonce
+				_ => str_err!("no handshake challenge issued"),
+			},
 		}
 		Ok(())
 	}
 
-	/// Handle Authorization::Saved in handshake request
-	fn handle_auth_saved(
-		&mut self,
-		nonce: [u8; 32],
-		signature: Signature,
-		pub_key: &[u8],
-	) -> DynResult {
+	/// Generate a new nonce for the client to sign with an already registered
+	/// public key and send it to the client
+	fn issue_challenge(&mut self, pub_key: Vec<u8>) -> DynResult {
 		use common::payloads::{HandshakeRes, PubKeyStatus};
 
+		let nonce: [u8; 32] = rand::random();
+		self.send(
+			MessageType::Handshake,
+			&HandshakeRes {
+				id: self.pub_key.pub_id,
+				status: PubKeyStatus::Challenge { nonce },
+			},
+		)?;
+		self.conn_state = ConnState::Challenged {
+			pub_key,
+			nonce,
+			issued: std::time::Instant::now(),
+		};
+		Ok(())
+	}
+
+	/// Verify the signature of a public key public ID and nonce issued by the
+	/// server
+	fn verify_challenge(
+		pub_id: &uuid::Uuid,
+		nonce: &[u8; 32],
+		signature: &Signature,
+		pub_key: &[u8],
+	) -> DynResult {
 		let pk = openssl::pkey::PKey::from_rsa(
 			openssl::rsa::Rsa::public_key_from_der(pub_key)?,
 		)?;
@@ -675,42 +1016,123 @@ impl MessageHandler {
 			openssl::hash::MessageDigest::sha256(),
 			&pk,
 		)?;
-		v.update(self.pub_key.pub_id.as_bytes())?;
-		v.update(&nonce)?;
+		v.update(pub_id.as_bytes())?;
+		v.update(nonce)?;
 		if !v.verify(&signature.0)? {
 			str_err!("invalid signature");
 		}
+		Ok(())
+	}
+
+	/// If the client's public key or IP is banned, send the client the ban
+	/// notice and disconnect it.
+	///
+	/// Returns, if the client is banned.
+	async fn check_ban(&mut self) -> DynResult<bool> {
+		let ban =
+			db::get_ban(Some(self.pub_key.priv_id), self.state.ip).await?;
+		Ok(match ban {
+			Some(ban) => {
+				self.ban(ban);
+				true
+			}
+			None => false,
+		})
+	}
+
+	/// Discard any buffered messages and have the client send the ban notice
+	/// and disconnect
+	fn ban(&mut self, ban: BanNotice) {
+		self.message = None;
+		self.open_post = None;
+		self.conn_state = ConnState::Banned;
+		self.client.do_send(super::Ban(ban));
+	}
 
+	/// Complete the handshake with the currently set public key
+	async fn accept_handshake(&mut self) -> DynResult {
+		use common::payloads::{HandshakeRes, PubKeyStatus};
+
+		if self.check_ban().await? {
+			return Ok(());
+		}
+
+		let staff_level =
+			db::get_staff(self.pub_key.priv_id).await?.map(|s| s.level);
+		self.state
+			.registry
+			.send(registry::SetPublicKey {
+				client: self.state.id,
+				pub_key: self.pub_key.priv_id,
+				staff: staff_level.is_some(),
+			})
+			.await??;
 		self.send(
 			MessageType::Handshake,
 			&HandshakeRes {
 				id: self.pub_key.pub_id,
-				status: PubKeyStatus::Accepted,
+				status: PubKeyStatus::Accepted { staff_level },
 			},
 		)?;
 		self.conn_state = ConnState::AcceptedHandshake;
 		Ok(())
 	}
 
-	/// Handle repeated handshake after request by server
-	fn handle_reshake(
-		&mut self,
-		mut dec: &mut Decoder,
-		pub_key: &[u8],
-	) -> DynResult {
-		match Self::decode_handshake(&mut dec)?.auth {
-			Authorization::Saved {
-				id: pub_id,
-				nonce,
-				signature,
-			} => {
-				if pub_id != self.pub_key.pub_id {
-					str_err!("different public key public id in reshake");
-				}
-				self.handle_auth_saved(nonce, signature, pub_key)?;
+	/// Perform a moderation action on a post or thread, if the client's public
+	/// key has a sufficient staff level
+	async fn moderate(&mut self, req: PostModeration) -> DynResult {
+		if req.reason.chars().count() > 200 {
+			str_err!("reason too long");
+		}
+		if let ModerationAction::BanPoster = req.action {
+			if req.length == 0 {
+				str_err!("ban duration not set");
 			}
-			_ => str_err!("invalid authorization variant"),
 		}
+
+		let staff = match db::get_staff(self.pub_key.priv_id).await? {
+			Some(s) if s.level >= req.action.required_level() => s,
+			_ => str_err!("insufficient staff level for {:?}", req.action),
+		};
+		let p = match db::moderate_post(&staff, &req).await? {
+			Some(p) => p,
+			None => str_err!("post not found: {}", req.id),
+		};
+		self.get_thread_feed(p.thread)
+			.await?
+			.do_send(feeds::ModeratePost {
+				loc: feeds::PostLocation {
+					id: req.id,
+					page: p.page,
+				},
+				entry: p.entry,
+			});
+		Ok(())
+	}
+
+	/// Replace the global server configurations. Propagation to all servers
+	/// and clients happens through the DB update notification.
+	async fn set_server_config(&self, conf: String) -> DynResult {
+		match db::get_staff(self.pub_key.priv_id).await? {
+			Some(s) if s.level >= ModerationLevel::Admin => (),
+			_ => str_err!("insufficient staff level to set server configs"),
+		};
+		db::write_config(&serde_json::from_str::<config::Config>(&conf)?).await
+	}
+
+	/// Perform a full-text search of closed posts and send the results to the
+	/// client. Searching too often is rejected without disconnecting the
+	/// client.
+	async fn search(&mut self, req: SearchReq) -> DynResult {
+		search::validate(&req)?;
+		if antispam::increment_search_score(self.pub_key.priv_id) {
+			return self.send(
+				MessageType::Search,
+				&payloads::SearchReply::RateLimited,
+			);
+		}
+		let res = search::search(req).await?;
+		self.send(MessageType::Search, &payloads::SearchReply::Results(res))?;
 		Ok(())
 	}
 
@@ -739,104 +1161,52 @@ impl MessageHandler {
 
 	/// Close the currently open post
 	async fn close_post(&mut self) -> DynResult {
-		use common::payloads::post_body::{Command, Node, PendingNode};
-
 		let p = self.open_post.take().ok_or_else(|| "no post open")?;
-		let mut body =
-			crate::body::parse(&p.body.iter().collect::<String>(), false);
+		self.state.registry.do_send(registry::SetOpenPost {
+			client: self.state.id,
+			post: None,
+		});
+		crate::body::close_post(
+			&p.feed,
+			p.loc,
+			crate::body::parse(&p.body.iter().collect::<String>(), false),
+		)
+		.await?;
+		Ok(())
+	}
+}
 
-		#[async_recursion::async_recursion]
-		async fn finalize_pending(n: &mut Node) -> DynResult {
-			use rand::prelude::*;
+/// Clear the open post of the client, if it matches the ID. Sent, when the
+/// post is closed by the server.
+pub struct ClearOpenPost(pub u64);
 
-			match n {
-				Node::Children(ch) => {
-					for ch in ch.iter_mut() {
-						finalize_pending(ch).await?
-					}
-				}
-				Node::Pending(p) => {
-					*n = match p {
-						PendingNode::Autobahn(h) => {
-							Node::Command(Command::Autobahn(*h))
-						}
-						PendingNode::Countdown(s) => {
-							Node::Command(Command::Countdown {
-								start: util::now(),
-								secs: *s,
-							})
-						}
-						PendingNode::Dice {
-							offset,
-							faces,
-							rolls,
-						} => Node::Command(Command::Dice {
-							offset: *offset,
-							faces: *faces,
-							results: {
-								let mut res =
-									Vec::with_capacity(*rolls as usize);
-
-								let mut rng = thread_rng();
-								for _ in 0..*rolls {
-									res.push(rng.gen::<u16>() % *faces + 1);
-								}
-
-								res
-							},
-						}),
-						PendingNode::EightBall => {
-							// TODO: read eightball choices from thread configs
-							static CHOICES: [&str; 5] = [
-								"Yes",
-								"No",
-								"Maybe",
-								"Anta baka?",
-								"Hell yeah, motherfucker!",
-							];
-
-							Node::Command(Command::EightBall(
-								CHOICES[random::<usize>() % CHOICES.len()]
-									.into(),
-							))
-						}
-						PendingNode::Flip => {
-							Node::Command(Command::Flip(random()))
-						}
-						PendingNode::PostLink(id) => {
-							match crate::body::post_location(*id).await? {
-								Some((thread, page)) => Node::PostLink {
-									id: *id,
-									thread,
-									page,
-								},
-								None => Node::Text(format!(">>{}", id)),
-							}
-						}
-						PendingNode::Pyu => Node::Command(Command::Pyu(
-							crate::db::increment_pcount().await?,
-						)),
-						PendingNode::PCount => Node::Command(Command::PCount(
-							crate::db::get_pcount().await?,
-						)),
-					}
-				}
-				_ => (),
-			};
-			Ok(())
-		}
+#[async_trait]
+impl AsyncHandler<ClearOpenPost> for MessageHandler {
+	type Error = ();
 
-		finalize_pending(&mut body).await?;
+	async fn handle(
+		&mut self,
+		ClearOpenPost(id): ClearOpenPost,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		if matches!(&self.open_post, Some(p) if p.loc.id == id) {
+			self.open_post = None;
+		}
+		Ok(())
+	}
+}
 
-		crate::db::close_post(p.loc.id, &body).await?;
-		p.feed.do_send(crate::feeds::ClosePost {
-			loc: PostLocation {
-				page: p.loc.page,
-				id: p.loc.id,
-			},
-			body,
-		});
+#[async_trait]
+impl AsyncHandler<util::Stop> for MessageHandler {
+	type Error = ();
 
+	async fn handle(
+		&mut self,
+		req: util::Stop,
+		ctx: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		ctx.stop();
+		req.done();
 		Ok(())
 	}
 }
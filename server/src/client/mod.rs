@@ -1,6 +1,9 @@
 mod client;
 mod message_handler;
-pub use client::{Client, Disconnect, SendMessage, SendMessageBatch};
+pub use client::{
+	Ban, Client, Disconnect, OpenPostClosed, Restart, SendMessage,
+	SendMessageBatch,
+};
 
 use crate::{
 	feeds::IndexFeed, mt_context::MTAddr, registry::Registry, str_err,
@@ -1,14 +1,14 @@
-use clap::Parser;
+use clap::{ArgEnum, Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, RwLock};
 
-// TODO: read configs from DB or fallback to default, if none
-
 lazy_static::lazy_static! {
 	/// Configurations for this specific application server
 	pub static ref SERVER: Server = Server::parse();
 
 	/// Global configurations. Wrapped for swapping whole.
+	/// Read from the DB on startup and reloaded on each update of the DB
+	/// record.
 	static ref CONFIG: RwLock<Arc<Config>> = Default::default();
 }
 
@@ -28,6 +28,30 @@ pub struct Server {
 	#[clap(short, long, env = "REVERSE_PROXIED")]
 	pub reverse_proxied: bool,
 
+	/// Directory containing captcha images in a subdirectory named after each
+	/// tag in the captcha tag pool
+	#[clap(long, default_value = "captchas", env = "CAPTCHA_DIR")]
+	pub captcha_dir: std::path::PathBuf,
+
+	/// Directory containing uploaded image source files and thumbnails in the
+	/// src and thumb subdirectories respectively
+	#[clap(long, default_value = "www/images", env = "IMAGE_DIR")]
+	pub image_dir: std::path::PathBuf,
+
+	/// Scheme and host the server is publicly reachable at. Used to build
+	/// absolute URLs for link previews and feeds.
+	#[clap(
+		long,
+		default_value = "http://127.0.0.1:8000",
+		env = "PUBLIC_ORIGIN"
+	)]
+	pub public_origin: String,
+
+	/// Seconds clients are told to wait before reconnecting, when the server
+	/// shuts down
+	#[clap(long, default_value = "5", env = "RECONNECT_DELAY")]
+	pub reconnect_delay: u32,
+
 	/// Lowest log message level to output to stderr.
 	// One of: ERROR WARN INFO DEBUG TRACE
 	#[cfg(debug_assertions)]
@@ -39,10 +63,159 @@ pub struct Server {
 	#[cfg(not(debug_assertions))]
 	#[clap(short, long, env = "LOG_LEVEL", default_value = "WARN")]
 	pub log_level: log::Level,
+
+	/// Action to perform. Defaults to starting the server.
+	#[clap(subcommand)]
+	pub command: Option<Command>,
+}
+
+/// Action to perform on execution
+#[derive(Subcommand)]
+pub enum Command {
+	/// Start the server
+	Serve,
+
+	/// Apply any pending database migrations and exit
+	Migrate,
+
+	/// Read or write the global configurations stored in the database
+	#[clap(subcommand)]
+	Config(ConfigCommand),
+
+	/// Manage staff accounts
+	#[clap(subcommand)]
+	Staff(StaffCommand),
+
+	/// Moderate threads
+	#[clap(subcommand)]
+	Thread(ThreadCommand),
+
+	/// Manage bans
+	#[clap(subcommand)]
+	Ban(BanCommand),
+
+	/// Build the full-text search index of closed posts, that are not yet
+	/// indexed, such as posts closed before search was added
+	IndexSearch,
+}
+
+/// Global configuration management actions
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+	/// Print the current global configurations as JSON to stdout
+	Get,
+
+	/// Replace the global configurations with JSON read from a file.
+	/// Omitted fields are set to their defaults.
+	/// All running servers are notified of the update.
+	Set {
+		/// Path to read the configurations from. Reads from stdin, if omitted.
+		path: Option<std::path::PathBuf>,
+	},
+}
+
+/// Staff account management actions
+#[derive(Subcommand)]
+pub enum StaffCommand {
+	/// Assign a moderation level to a public key, replacing any existing
+	Add {
+		/// Private ID of the public key
+		public_key: u64,
+
+		#[clap(arg_enum)]
+		level: StaffLevel,
+
+		/// Title displayed in the moderation log instead of the level
+		#[clap(short, long)]
+		title: Option<String>,
+	},
+
+	/// Remove the staff account of a public key
+	Remove {
+		/// Private ID of the public key
+		public_key: u64,
+	},
+}
+
+/// Moderation level assignable to a staff account
+#[derive(ArgEnum, Clone, Copy)]
+pub enum StaffLevel {
+	Janitor,
+	Moderator,
+	Admin,
+}
+
+impl From<StaffLevel> for common::payloads::moderation::ModerationLevel {
+	fn from(l: StaffLevel) -> Self {
+		match l {
+			StaffLevel::Janitor => Self::Janitor,
+			StaffLevel::Moderator => Self::Moderator,
+			StaffLevel::Admin => Self::Admin,
+		}
+	}
+}
+
+/// Thread moderation actions
+#[derive(Subcommand)]
+pub enum ThreadCommand {
+	/// Delete a thread and all of its posts
+	Delete {
+		/// ID of the thread
+		id: u64,
+	},
+
+	/// Lock a thread, preventing any new replies
+	Lock {
+		/// ID of the thread
+		id: u64,
+
+		/// Unlock the thread instead
+		#[clap(short, long)]
+		unlock: bool,
+	},
+}
+
+/// Ban management actions
+#[derive(Subcommand)]
+pub enum BanCommand {
+	/// Ban a public key or IP address range.
+	/// Exactly one of --public-key and --ip must be set.
+	Add {
+		/// Private ID of the public key to ban
+		#[clap(
+			short,
+			long,
+			conflicts_with = "ip",
+			required_unless_present = "ip"
+		)]
+		public_key: Option<u64>,
+
+		/// IP address or CIDR range to ban
+		#[clap(short, long)]
+		ip: Option<String>,
+
+		/// Duration of the ban in hours
+		#[clap(short, long)]
+		hours: u32,
+
+		/// Reason for the ban displayed to the banned user
+		#[clap(short, long, default_value = "")]
+		reason: String,
+	},
+
+	/// List all active bans
+	List,
+
+	/// Lift a ban
+	Remove {
+		/// ID of the ban
+		id: u64,
+	},
 }
 
 /// Antispam scores for various client actions
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct SpamScores {
 	/// Score per unicode character for any post body modification
 	pub character: usize,
@@ -52,6 +225,9 @@ pub struct SpamScores {
 
 	/// Score for creating a post
 	pub post_creation: usize,
+
+	/// Score for performing a full-text search
+	pub search: usize,
 }
 
 impl Default for SpamScores {
@@ -61,12 +237,59 @@ impl Default for SpamScores {
 			character: 85,
 			image: 7500,
 			post_creation: 7500,
+			search: 5000,
+		}
+	}
+}
+
+/// Difficulty bounds of proof of work challenges in required leading zero bits
+/// of the hash
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ProofOfWork {
+	/// Difficulty for public keys with no spam score
+	pub base_difficulty: u8,
+
+	/// Maximum difficulty reached with an increasing spam score
+	pub max_difficulty: u8,
+}
+
+impl Default for ProofOfWork {
+	#[inline]
+	fn default() -> Self {
+		Self {
+			base_difficulty: 16,
+			max_difficulty: 24,
+		}
+	}
+}
+
+/// Limits after which open posts are considered abandoned and are closed
+/// automatically
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct OpenPostLimits {
+	/// Seconds a post can stay open for
+	pub max_age: u32,
+
+	/// Seconds a post can stay open for, after the last connection using the
+	/// public key the post was created with disconnected
+	pub max_owner_absence: u32,
+}
+
+impl Default for OpenPostLimits {
+	#[inline]
+	fn default() -> Self {
+		Self {
+			max_age: 30 * 60,
+			max_owner_absence: 5 * 60,
 		}
 	}
 }
 
 /// Global server configurations
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct Config {
 	/// Global server configurations exposed to the client.
 	///
@@ -79,7 +302,19 @@ pub struct Config {
 	/// Antispam scores for various client actions
 	pub spam_scores: SpamScores,
 
-	/// Booru tags for the captcha pool
+	/// Difficulty of proof of work challenges, if enabled with
+	/// AntispamMode::ProofOfWork
+	pub proof_of_work: ProofOfWork,
+
+	/// Limits for automatically closing abandoned open posts
+	pub open_post_limits: OpenPostLimits,
+
+	/// Maximum number of posts in a cyclic thread, including the OP. The
+	/// oldest replies past this limit are deleted.
+	pub cyclic_thread_limit: u32,
+
+	/// Tags for the captcha pool. Images for each tag are read from a
+	/// subdirectory of Server::captcha_dir named after the tag.
 	pub captcha_tags: Vec<String>,
 }
 
@@ -90,6 +325,9 @@ impl Default for Config {
 			public: Default::default(),
 			disable_robots: Default::default(),
 			spam_scores: Default::default(),
+			proof_of_work: Default::default(),
+			open_post_limits: Default::default(),
+			cyclic_thread_limit: 1000,
 			captcha_tags: vec![
 				"patchouli_knowledge".into(),
 				"cirno".into(),
@@ -105,10 +343,12 @@ pub fn get() -> Arc<Config> {
 	CONFIG.read().unwrap().clone()
 }
 
-/// Set the configurations to a new value
+/// Set the configurations to a new value.
+///
+/// Does not propagate the change to clients. Send registry::BroadcastConfigs
+/// for that.
 #[cold]
 pub fn set(c: Config) {
-	// TODO: send new configs to all clients
 	let c = Arc::new(c);
 	*CONFIG.write().unwrap() = c;
 }
@@ -0,0 +1,89 @@
+use super::{pool, Timer};
+use crate::util::DynResult;
+use std::{collections::HashMap, time::Duration};
+
+/// Return the current spam score of a public key in milliseconds
+pub async fn get_spam_score(pub_key: u64) -> Result<u64, sqlx::Error> {
+	let _timer = Timer::new("get_spam_score");
+	Ok(sqlx::query!(
+		"select (extract(epoch from expires - now()) * 1000)::bigint score
+		from spam_scores
+		where public_key = $1 and expires > now()",
+		pub_key as i64,
+	)
+	.fetch_optional(&pool())
+	.await?
+	.map(|r| r.score)
+	.flatten()
+	.unwrap_or_default() as u64)
+}
+
+/// Add buffered spam score increments to the sliding spam score windows of
+/// public keys
+pub async fn flush_spam_scores(scores: HashMap<u64, Duration>) -> DynResult {
+	let _timer = Timer::new("flush_spam_scores");
+	let mut scores: Vec<(u64, Duration)> = scores.into_iter().collect();
+
+	// Sort by ID for more sequential DB access
+	scores.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+	let mut tx = pool().begin().await?;
+	for (pub_key, score) in scores {
+		sqlx::query!(
+			"insert into spam_scores as s (public_key, expires)
+			values ($1, now() + $2 * interval '1 millisecond')
+			on conflict (public_key)
+			do update set expires =
+				greatest(s.expires, now()) + $2 * interval '1 millisecond'",
+			pub_key as i64,
+			score.as_millis() as f64,
+		)
+		.execute(&mut tx)
+		.await?;
+	}
+	tx.commit().await?;
+	Ok(())
+}
+
+/// Record a public key as having solved a captcha and reset its spam score
+pub async fn record_valid_captcha(pub_key: u64) -> DynResult {
+	let _timer = Timer::new("record_valid_captcha");
+	let mut tx = pool().begin().await?;
+	sqlx::query!(
+		"insert into last_solved_captchas (public_key, expires)
+		values ($1, now() + interval '3 hours')
+		on conflict (public_key)
+		do update set expires = excluded.expires",
+		pub_key as i64,
+	)
+	.execute(&mut tx)
+	.await?;
+	sqlx::query!(
+		"delete from spam_scores
+		where public_key = $1",
+		pub_key as i64,
+	)
+	.execute(&mut tx)
+	.await?;
+	tx.commit().await?;
+	Ok(())
+}
+
+/// Return, if a public key has solved a captcha within the last 3 hours
+pub async fn solved_captcha_recently(
+	pub_key: u64,
+) -> Result<bool, sqlx::Error> {
+	let _timer = Timer::new("solved_captcha_recently");
+	Ok(sqlx::query!(
+		"select exists (
+			select
+			from last_solved_captchas
+			where public_key = $1 and expires > now()
+		) has",
+		pub_key as i64,
+	)
+	.fetch_one(&pool())
+	.await?
+	.has
+	.unwrap_or_default())
+}
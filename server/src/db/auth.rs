@@ -1,4 +1,4 @@
-use super::pool;
+use super::{pool, Timer};
 use crate::util::DynResult;
 use rand::prelude::*;
 use uuid::Uuid;
@@ -9,6 +9,7 @@ use uuid::Uuid;
 pub async fn register_public_key(
 	pub_key: &[u8],
 ) -> DynResult<(u64, Uuid, bool)> {
+	let _timer = Timer::new("register_public_key");
 	let pub_id: Uuid = {
 		let mut buf: [u8; 16] = Default::default();
 		thread_rng().try_fill_bytes(&mut buf)?;
@@ -46,6 +47,7 @@ pub async fn register_public_key(
 pub async fn get_public_key(
 	pub_id: &Uuid,
 ) -> Result<Option<(u64, Vec<u8>)>, sqlx::Error> {
+	let _timer = Timer::new("get_public_key");
 	sqlx::query!(
 		"select id, public_key
 		from public_keys
@@ -0,0 +1,152 @@
+use super::{listen, notify, pool, Timer};
+use crate::util::DynResult;
+use common::payloads::moderation::BanNotice;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// Return the longest lasting active ban of a public key or an IP, if any
+pub async fn get_ban(
+	pub_key: Option<u64>,
+	ip: IpAddr,
+) -> Result<Option<BanNotice>, sqlx::Error> {
+	let _timer = Timer::new("get_ban");
+	Ok(sqlx::query!(
+		"select reason, to_unix(expires) expires
+		from bans
+		where (public_key = $1 or ip >>= $2::text::inet)
+			and expires > now()
+		order by expires desc
+		limit 1",
+		pub_key.map(|k| k as i64),
+		ip.to_string(),
+	)
+	.fetch_optional(&pool())
+	.await?
+	.map(|r| BanNotice {
+		reason: r.reason,
+		expires: r.expires.unwrap_or_default() as u32,
+	}))
+}
+
+/// Active ban of a public key or an IP range
+#[derive(Debug)]
+pub struct BanRecord {
+	pub id: u64,
+
+	/// Private ID of the banned public key, if any
+	pub public_key: Option<u64>,
+
+	/// Banned IP range, if any
+	pub ip: Option<String>,
+
+	/// Private ID of the public key of the staff member, that issued the ban,
+	/// if any
+	pub staff: Option<u64>,
+
+	pub reason: String,
+
+	/// Unix timestamp of ban expiry
+	pub expires: u32,
+}
+
+/// Ban applied outside of running servers, that they must enforce on their
+/// connected clients
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BanNotification {
+	/// Private ID of the banned public key, if any
+	pub public_key: Option<u64>,
+
+	/// Banned IP range, if any
+	pub ip: Option<String>,
+
+	pub notice: BanNotice,
+}
+
+/// Ban a public key or an IP range for the specified duration in seconds,
+/// notify running servers and return the ban's ID.
+/// Exactly one of pub_key and ip must be set.
+pub async fn insert_ban(
+	pub_key: Option<u64>,
+	ip: Option<&str>,
+	duration: u64,
+	reason: &str,
+) -> DynResult<u64> {
+	let _timer = Timer::new("insert_ban");
+	let mut tx = pool().begin().await?;
+	let r = sqlx::query!(
+		"insert into bans (public_key, ip, expires, reason)
+		values (
+			$1,
+			$2::text::inet,
+			now() + $3 * interval '1 second',
+			$4
+		)
+		returning id, ip::text ip, to_unix(expires) expires",
+		pub_key.map(|k| k as i64),
+		ip,
+		duration as f64,
+		reason,
+	)
+	.fetch_one(&mut tx)
+	.await?;
+	notify(
+		&mut tx,
+		"ban.inserted",
+		&BanNotification {
+			public_key: pub_key,
+			ip: r.ip,
+			notice: BanNotice {
+				reason: reason.into(),
+				expires: r.expires.unwrap_or_default() as u32,
+			},
+		},
+	)
+	.await?;
+	tx.commit().await?;
+
+	Ok(r.id as u64)
+}
+
+/// Listen for bans applied outside of running servers and call on_ban with
+/// each. Only returns on error.
+pub async fn listen_for_bans(on_ban: impl FnMut(BanNotification)) -> DynResult {
+	listen("ban.inserted", on_ban).await
+}
+
+/// Return all active bans ordered by expiry
+pub async fn get_bans() -> Result<Vec<BanRecord>, sqlx::Error> {
+	let _timer = Timer::new("get_bans");
+	Ok(sqlx::query!(
+		"select id, public_key, ip::text ip, staff, reason,
+			to_unix(expires) expires
+		from bans
+		where expires > now()
+		order by expires",
+	)
+	.fetch_all(&pool())
+	.await?
+	.into_iter()
+	.map(|r| BanRecord {
+		id: r.id as u64,
+		public_key: r.public_key.map(|k| k as u64),
+		ip: r.ip,
+		staff: r.staff.map(|k| k as u64),
+		reason: r.reason,
+		expires: r.expires.unwrap_or_default() as u32,
+	})
+	.collect())
+}
+
+/// Lift a ban. Returns false, if there was no such ban.
+pub async fn delete_ban(id: u64) -> Result<bool, sqlx::Error> {
+	let _timer = Timer::new("delete_ban");
+	Ok(sqlx::query!(
+		"delete from bans
+		where id = $1",
+		id as i64,
+	)
+	.execute(&pool())
+	.await?
+	.rows_affected()
+		!= 0)
+}
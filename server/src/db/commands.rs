@@ -1,8 +1,9 @@
-use super::pool;
+use super::{pool, Timer};
 use crate::util::DynResult;
 
 /// Increase pcount by one and return current count
 pub async fn increment_pcount() -> DynResult<u64> {
+	let _timer = Timer::new("increment_pcount");
 	Ok(sqlx::query!(
 		"insert into main as m (key, val)
 		values ('pyu_count', '1')
@@ -19,6 +20,7 @@ pub async fn increment_pcount() -> DynResult<u64> {
 
 /// Return current pyu count
 pub async fn get_pcount() -> DynResult<u64> {
+	let _timer = Timer::new("get_pcount");
 	Ok(sqlx::query!(
 		"select coalesce(
 			(
@@ -0,0 +1,47 @@
+use super::{pool, Timer};
+use crate::{config::Config, util::DynResult};
+
+/// Read the global configuration from the DB, if any is stored
+pub async fn get_config() -> DynResult<Option<Config>> {
+	let _timer = Timer::new("get_config");
+	sqlx::query!(
+		"select val
+		from main
+		where key = 'config'",
+	)
+	.fetch_optional(&pool())
+	.await?
+	.map(|r| Ok(serde_json::from_value(r.val)?))
+	.transpose()
+}
+
+/// Write the global configuration to the DB. All running servers are notified
+/// of the update.
+pub async fn write_config(conf: &Config) -> DynResult {
+	let _timer = Timer::new("write_config");
+	sqlx::query!(
+		"insert into main (key, val)
+		values ('config', $1)
+		on conflict (key) do update
+			set val = excluded.val",
+		serde_json::to_value(conf)?,
+	)
+	.execute(&pool())
+	.await?;
+	Ok(())
+}
+
+/// Listen for global configuration updates in the DB and call on_update with
+/// each new configuration. Only returns on error.
+pub async fn listen_for_config_updates(
+	mut on_update: impl FnMut(Config),
+) -> DynResult {
+	let mut l = sqlx::postgres::PgListener::connect_with(&pool()).await?;
+	l.listen("config.updated").await?;
+	loop {
+		l.recv().await?;
+		if let Some(conf) = get_config().await? {
+			on_update(conf);
+		}
+	}
+}
@@ -1,15 +1,27 @@
+mod antispam;
 mod auth;
+mod bans;
 mod commands;
+mod config;
+mod moderation;
 mod posts;
 mod threads;
+mod upkeep;
 
+pub use antispam::*;
 pub use auth::*;
+pub use bans::*;
 pub use commands::*;
+pub use config::*;
+pub use moderation::*;
 pub use posts::*;
 pub use threads::*;
+pub use upkeep::*;
 
 use crate::util::DynResult;
-use sqlx::postgres::PgPool;
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::{postgres::PgPool, Postgres, Transaction};
+use std::time::Instant;
 
 static mut POOL: Option<PgPool> = None;
 
@@ -25,8 +37,74 @@ pub async fn open() -> DynResult {
 	Ok(())
 }
 
+/// Check the database is reachable
+pub async fn ping() -> DynResult {
+	use sqlx::Connection;
+
+	pool().acquire().await?.ping().await?;
+	Ok(())
+}
+
 /// Get a handle on the connection pool
 #[inline]
 fn pool() -> PgPool {
 	unsafe { POOL.clone().unwrap() }
 }
+
+/// Records the duration of a DB operation under name, when dropped.
+/// Bind it to a variable at the start of the operation.
+struct Timer {
+	name: &'static str,
+	start: Instant,
+}
+
+impl Timer {
+	#[inline]
+	fn new(name: &'static str) -> Self {
+		Self {
+			name,
+			start: Instant::now(),
+		}
+	}
+}
+
+impl Drop for Timer {
+	fn drop(&mut self) {
+		crate::metrics::observe_db_query(self.name, self.start.elapsed());
+	}
+}
+
+/// Notify running servers on a channel with a JSON-encoded payload.
+/// The notification is delivered on transaction commit.
+async fn notify(
+	tx: &mut Transaction<'static, Postgres>,
+	channel: &str,
+	payload: &impl Serialize,
+) -> DynResult {
+	sqlx::query!(
+		"select from pg_notify($1, $2)",
+		channel,
+		serde_json::to_string(payload)?,
+	)
+	.execute(&mut *tx)
+	.await?;
+	Ok(())
+}
+
+/// Listen for JSON-encoded notifications on a channel and call on_notify with
+/// each decoded payload. Only returns on error.
+async fn listen<T>(channel: &str, mut on_notify: impl FnMut(T)) -> DynResult
+where
+	T: DeserializeOwned,
+{
+	let mut l = sqlx::postgres::PgListener::connect_with(&pool()).await?;
+	l.listen(channel).await?;
+	loop {
+		match serde_json::from_str(l.recv().await?.payload()) {
+			Ok(payload) => on_notify(payload),
+			Err(e) => {
+				log::error!("invalid {} notification payload: {}", channel, e)
+			}
+		}
+	}
+}
@@ -0,0 +1,512 @@
+use super::{listen, notify, pool, BanNotification, Timer};
+use crate::util::DynResult;
+use common::payloads::{
+	moderation::{
+		BanNotice, ModerationAction, ModerationEntry, ModerationLevel,
+		PostModeration, PostModerationEntry,
+	},
+	post_body::Node,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+
+/// Staff account of a public key
+#[derive(Debug, Clone)]
+pub struct StaffMember {
+	/// Private ID of the public key
+	pub public_key: u64,
+
+	pub level: ModerationLevel,
+
+	/// Title displayed in the moderation log instead of the level, if any
+	pub title: Option<String>,
+}
+
+/// Convert a staff level stored in the DB
+fn level_from_db(level: i16) -> DynResult<ModerationLevel> {
+	use ModerationLevel::*;
+
+	Ok(match level {
+		1 => Janitor,
+		2 => Moderator,
+		3 => Admin,
+		_ => return Err(format!("invalid staff level: {}", level).into()),
+	})
+}
+
+/// Return the staff account of a public key, if any
+pub async fn get_staff(pub_key: u64) -> DynResult<Option<StaffMember>> {
+	let _timer = Timer::new("get_staff");
+	sqlx::query!(
+		"select level, title
+		from staff
+		where public_key = $1",
+		pub_key as i64,
+	)
+	.fetch_optional(&pool())
+	.await?
+	.map(|r| {
+		Ok(StaffMember {
+			public_key: pub_key,
+			level: level_from_db(r.level)?,
+			title: r.title,
+		})
+	})
+	.transpose()
+}
+
+/// Change of the staff status of a public key, that running servers must
+/// apply to its connected clients
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StaffNotification {
+	/// Private ID of the public key
+	pub public_key: u64,
+
+	/// The public key has a staff account
+	pub staff: bool,
+}
+
+/// Assign a moderation level and title to a public key, replacing any
+/// existing staff account, and notify running servers
+pub async fn set_staff(
+	pub_key: u64,
+	level: ModerationLevel,
+	title: Option<&str>,
+) -> DynResult {
+	let _timer = Timer::new("set_staff");
+	let mut tx = pool().begin().await?;
+	sqlx::query!(
+		"insert into staff (public_key, level, title)
+		values ($1, $2, $3)
+		on conflict (public_key) do update
+			set level = excluded.level,
+				title = excluded.title",
+		pub_key as i64,
+		level as i16,
+		title,
+	)
+	.execute(&mut tx)
+	.await?;
+	notify(
+		&mut tx,
+		"staff.updated",
+		&StaffNotification {
+			public_key: pub_key,
+			staff: true,
+		},
+	)
+	.await?;
+	tx.commit().await?;
+	Ok(())
+}
+
+/// Remove the staff account of a public key and notify running servers.
+/// Returns false, if there was none.
+pub async fn remove_staff(pub_key: u64) -> DynResult<bool> {
+	let _timer = Timer::new("remove_staff");
+	let mut tx = pool().begin().await?;
+	let removed = sqlx::query!(
+		"delete from staff
+		where public_key = $1",
+		pub_key as i64,
+	)
+	.execute(&mut tx)
+	.await?
+	.rows_affected()
+		!= 0;
+	if removed {
+		notify(
+			&mut tx,
+			"staff.updated",
+			&StaffNotification {
+				public_key: pub_key,
+				staff: false,
+			},
+		)
+		.await?;
+	}
+	tx.commit().await?;
+	Ok(removed)
+}
+
+/// Listen for staff account changes and call on_update with each.
+/// Only returns on error.
+pub async fn listen_for_staff_updates(
+	on_update: impl FnMut(StaffNotification),
+) -> DynResult {
+	listen("staff.updated", on_update).await
+}
+
+/// Moderation action applied to a post in the DB
+#[derive(Debug)]
+pub struct ModeratedPost {
+	pub thread: u64,
+	pub page: u32,
+
+	/// Entry appended to the post's moderation log
+	pub entry: ModerationEntry,
+}
+
+/// Apply a moderation action to a post or the thread of an OP and record it
+/// in the post's moderation log.
+/// Returns None, if the post does not exist.
+pub async fn moderate_post(
+	staff: &StaffMember,
+	req: &PostModeration,
+) -> DynResult<Option<ModeratedPost>> {
+	let _timer = Timer::new("moderate_post");
+	use ModerationAction::*;
+
+	let id = req.id;
+	let mut tx = pool().begin().await?;
+	let loc = match req.action {
+		DeletePost => sqlx::query!(
+			"update posts
+			set deleted = true
+			where id = $1
+			returning thread, page",
+			id as i64,
+		)
+		.fetch_optional(&mut tx)
+		.await?
+		.map(|r| (r.thread as u64, r.page as u32)),
+		DeleteImage => sqlx::query!(
+			"update posts
+			set
+				image = null,
+				image_name = '',
+				image_spoilered = false
+			where id = $1
+			returning thread, page",
+			id as i64,
+		)
+		.fetch_optional(&mut tx)
+		.await?
+		.map(|r| (r.thread as u64, r.page as u32)),
+		SpoilerImage => sqlx::query!(
+			"update posts
+			set image_spoilered = true
+			where id = $1 and image is not null
+			returning thread, page",
+			id as i64,
+		)
+		.fetch_optional(&mut tx)
+		.await?
+		.map(|r| (r.thread as u64, r.page as u32)),
+		PurgePost => sqlx::query!(
+			"update posts
+			set
+				deleted = true,
+				open = false,
+				body = $2,
+				image = null,
+				image_name = '',
+				image_spoilered = false
+			where id = $1
+			returning thread, page",
+			id as i64,
+			serde_json::to_value(Node::Empty)?,
+		)
+		.fetch_optional(&mut tx)
+		.await?
+		.map(|r| (r.thread as u64, r.page as u32)),
+		ShadowBinPost => sqlx::query!(
+			"update posts
+			set shadow_binned = true
+			where id = $1
+			returning thread, page",
+			id as i64,
+		)
+		.fetch_optional(&mut tx)
+		.await?
+		.map(|r| (r.thread as u64, r.page as u32)),
+		LockThread(locked) => sqlx::query!(
+			"update threads
+			set locked = $2
+			where id = $1
+			returning id",
+			id as i64,
+			locked,
+		)
+		.fetch_optional(&mut tx)
+		.await?
+		.map(|r| (r.id as u64, 0)),
+		StickyThread(sticky) => sqlx::query!(
+			"update threads
+			set sticky = $2
+			where id = $1
+			returning id",
+			id as i64,
+			sticky,
+		)
+		.fetch_optional(&mut tx)
+		.await?
+		.map(|r| (r.id as u64, 0)),
+		CyclicThread(cyclic) => sqlx::query!(
+			"update threads
+			set cyclic = $2
+			where id = $1
+			returning id",
+			id as i64,
+			cyclic,
+		)
+		.fetch_optional(&mut tx)
+		.await?
+		.map(|r| (r.id as u64, 0)),
+		BanPoster => {
+			ban_poster(
+				&mut tx,
+				id,
+				Some(staff.public_key),
+				req.length,
+				&req.reason,
+				true,
+			)
+			.await?
+		}
+	};
+	let (thread, page) = match loc {
+		Some(loc) => loc,
+		None => return Ok(None),
+	};
+
+	let entry = log_moderation(
+		&mut tx,
+		id,
+		req.action,
+		Moderator::Staff(staff),
+		req.length,
+		&req.reason,
+	)
+	.await?;
+	tx.commit().await?;
+
+	Ok(Some(ModeratedPost {
+		thread,
+		page,
+		entry,
+	}))
+}
+
+/// Ban the public key of a post's author for the post's #autobahn command and
+/// record it in the post's moderation log.
+/// Returns None, if the post does not exist.
+pub(super) async fn self_ban(
+	tx: &mut Transaction<'static, Postgres>,
+	id: u64,
+	length: u32,
+	reason: &str,
+) -> DynResult<Option<ModeratedPost>> {
+	let (thread, page) =
+		match ban_poster(tx, id, None, length, reason, false).await? {
+			Some(p) => p,
+			None => return Ok(None),
+		};
+	let entry = log_moderation(
+		tx,
+		id,
+		ModerationAction::BanPoster,
+		Moderator::Author,
+		length,
+		reason,
+	)
+	.await?;
+
+	Ok(Some(ModeratedPost {
+		thread,
+		page,
+		entry,
+	}))
+}
+
+/// Ban the public key and, if ban_ip, the IP a post was created with, and
+/// notify running servers of the bans.
+/// Returns the thread and page of the post, if it exists.
+async fn ban_poster(
+	tx: &mut Transaction<'static, Postgres>,
+	id: u64,
+	staff: Option<u64>,
+	length: u32,
+	reason: &str,
+	ban_ip: bool,
+) -> DynResult<Option<(u64, u32)>> {
+	let r = match sqlx::query!(
+		"select thread, page
+		from posts
+		where id = $1",
+		id as i64,
+	)
+	.fetch_optional(&mut *tx)
+	.await?
+	{
+		Some(r) => r,
+		None => return Ok(None),
+	};
+
+	let mut bans = Vec::with_capacity(2);
+	if let Some(b) = sqlx::query!(
+		"insert into bans (public_key, staff, reason, expires)
+		select public_key, $2, $3, now() + $4 * interval '1 second'
+		from posts
+		where id = $1 and public_key is not null
+		returning public_key, to_unix(expires) expires",
+		id as i64,
+		staff.map(|s| s as i64),
+		reason,
+		length as f64,
+	)
+	.fetch_optional(&mut *tx)
+	.await?
+	{
+		bans.push(BanNotification {
+			public_key: b.public_key.map(|k| k as u64),
+			ip: None,
+			notice: BanNotice {
+				reason: reason.into(),
+				expires: b.expires.unwrap_or_default() as u32,
+			},
+		});
+	}
+	if ban_ip {
+		if let Some(b) = sqlx::query!(
+			"insert into bans (ip, staff, reason, expires)
+			select ip, $2, $3, now() + $4 * interval '1 second'
+			from posts
+			where id = $1 and ip is not null
+			returning ip::text ip, to_unix(expires) expires",
+			id as i64,
+			staff.map(|s| s as i64),
+			reason,
+			length as f64,
+		)
+		.fetch_optional(&mut *tx)
+		.await?
+		{
+			bans.push(BanNotification {
+				public_key: None,
+				ip: b.ip,
+				notice: BanNotice {
+					reason: reason.into(),
+					expires: b.expires.unwrap_or_default() as u32,
+				},
+			});
+		}
+	}
+	for b in bans {
+		notify(tx, "ban.inserted", &b).await?;
+	}
+
+	Ok(Some((r.thread as u64, r.page as u32)))
+}
+
+/// Performer of a moderation action
+enum Moderator<'a> {
+	/// Author of the post
+	Author,
+
+	/// Staff member
+	Staff(&'a StaffMember),
+
+	/// Administrative command run on the server
+	Admin,
+}
+
+/// Append an entry to a post's moderation log
+async fn log_moderation(
+	tx: &mut Transaction<'static, Postgres>,
+	id: u64,
+	action: ModerationAction,
+	by: Moderator<'_>,
+	length: u32,
+	reason: &str,
+) -> DynResult<ModerationEntry> {
+	let (staff, staff_level, staff_title) = match by {
+		Moderator::Author => (None, None, None),
+		Moderator::Staff(s) => {
+			(Some(s.public_key), Some(s.level), s.title.clone())
+		}
+		Moderator::Admin => (None, Some(ModerationLevel::Admin), None),
+	};
+	let created_on = sqlx::query!(
+		"insert into moderation_log (
+			post,
+			action,
+			staff,
+			staff_level,
+			staff_title,
+			length,
+			reason
+		)
+		values ($1, $2, $3, $4, $5, $6, $7)
+		returning to_unix(created_on) created_on",
+		id as i64,
+		serde_json::to_value(action)?,
+		staff.map(|k| k as i64),
+		staff_level.map(|l| l as i16),
+		staff_title.clone(),
+		length as i64,
+		reason,
+	)
+	.fetch_one(&mut *tx)
+	.await?
+	.created_on
+	.unwrap_or_default() as u32;
+
+	Ok(ModerationEntry {
+		action,
+		staff_level,
+		staff_title,
+		length,
+		reason: reason.into(),
+		created_on,
+	})
+}
+
+/// Lock or unlock a thread by an administrative command, record it in the
+/// OP's moderation log as performed by the admin and notify running servers.
+/// Returns false, if the thread does not exist.
+pub async fn set_thread_locked(id: u64, locked: bool) -> DynResult<bool> {
+	let _timer = Timer::new("set_thread_locked");
+	let mut tx = pool().begin().await?;
+	if sqlx::query!(
+		"update threads
+		set locked = $2
+		where id = $1",
+		id as i64,
+		locked,
+	)
+	.execute(&mut tx)
+	.await?
+	.rows_affected()
+		== 0
+	{
+		return Ok(false);
+	}
+
+	let entry = log_moderation(
+		&mut tx,
+		id,
+		ModerationAction::LockThread(locked),
+		Moderator::Admin,
+		0,
+		"",
+	)
+	.await?;
+	notify(
+		&mut tx,
+		"thread.moderated",
+		&PostModerationEntry { id, entry },
+	)
+	.await?;
+	tx.commit().await?;
+
+	Ok(true)
+}
+
+/// Listen for moderation actions applied to threads outside of running servers
+/// and call on_moderate with each. Only returns on error.
+pub async fn listen_for_thread_moderation(
+	on_moderate: impl FnMut(PostModerationEntry),
+) -> DynResult {
+	listen("thread.moderated", on_moderate).await
+}
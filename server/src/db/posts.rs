@@ -1,7 +1,7 @@
-use super::pool;
+use super::{pool, ModeratedPost, Timer};
 use crate::util::DynResult;
-use common::payloads::post_body::Node;
-use std::{collections::HashMap, sync::Arc};
+use common::payloads::{post_body::Node, Post};
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
 
 // Common params for both post and thread insertion
 pub struct PostInsertParams<'a> {
@@ -10,11 +10,15 @@ pub struct PostInsertParams<'a> {
 	pub trip: Option<&'a str>,
 	pub flag: Option<&'a str>,
 	pub body: &'a Node,
+
+	/// IP the post is created from
+	pub ip: IpAddr,
 }
 
 pub async fn write_open_post_bodies(
 	bodies: HashMap<u64, Arc<Node>>,
 ) -> DynResult {
+	let _timer = Timer::new("write_open_post_bodies");
 	let mut bodies: Vec<(u64, Arc<Node>)> = bodies.into_iter().collect();
 
 	// Sort by ID for more sequential DB access
@@ -36,21 +40,59 @@ pub async fn write_open_post_bodies(
 	Ok(())
 }
 
-/// Close open post and set its body
-pub async fn close_post(id: u64, body: &Node) -> DynResult {
-	sqlx::query!(
+/// Ban of a post's author applied on closing the post
+pub struct SelfBan<'a> {
+	/// Duration of the ban in seconds
+	pub length: u32,
+
+	pub reason: &'a str,
+}
+
+/// Closed post with any applied self ban
+pub struct ClosedPost {
+	pub self_ban: Option<ModeratedPost>,
+}
+
+/// Close open post and set its body and full-text search index built from
+/// `text`. If `self_ban` is set, the post's author is banned in the same
+/// transaction.
+/// Returns None, if the post was already closed.
+pub async fn close_post(
+	id: u64,
+	body: &Node,
+	text: &str,
+	self_ban: Option<SelfBan<'_>>,
+) -> DynResult<Option<ClosedPost>> {
+	let _timer = Timer::new("close_post");
+	let mut tx = pool().begin().await?;
+	if sqlx::query!(
 		"update posts
 		set
 			open = false,
-			body = $2
-		where id = $1",
+			body = $2,
+			search = to_tsvector('simple', $3)
+		where id = $1 and open = true",
 		id as i64,
 		serde_json::to_value(body)?,
+		text,
 	)
-	.execute(&pool())
-	.await?;
+	.execute(&mut tx)
+	.await?
+	.rows_affected()
+		== 0
+	{
+		return Ok(None);
+	}
 
-	Ok(())
+	let self_ban = match self_ban {
+		Some(b) => {
+			super::moderation::self_ban(&mut tx, id, b.length, b.reason).await?
+		}
+		None => None,
+	};
+	tx.commit().await?;
+
+	Ok(Some(ClosedPost { self_ban }))
 }
 
 /// Insert post into the database and return its ID and page
@@ -59,6 +101,7 @@ pub async fn insert_post<'a>(
 	sage: bool,
 	p: &PostInsertParams<'a>,
 ) -> DynResult<(u64, u32)> {
+	let _timer = Timer::new("insert_post");
 	let r = sqlx::query!(
 		"insert into posts (
 			thread,
@@ -67,7 +110,8 @@ pub async fn insert_post<'a>(
 			trip,
 			flag,
 			sage,
-			body
+			body,
+			ip
 		)
 		values (
 			$1,
@@ -76,7 +120,8 @@ pub async fn insert_post<'a>(
 			$4,
 			$5,
 			$6,
-			$7
+			$7,
+			$8::text::inet
 		)
 		returning id, page",
 		thread as i64,
@@ -86,6 +131,7 @@ pub async fn insert_post<'a>(
 		p.flag,
 		sage,
 		serde_json::to_value(p.body)?,
+		p.ip.to_string(),
 	)
 	.fetch_one(&pool())
 	.await?;
@@ -93,10 +139,135 @@ pub async fn insert_post<'a>(
 	Ok((r.id as u64, r.page as u32))
 }
 
+/// Return up to limit closed posts with IDs greater than after, that have no
+/// full-text search index, as (id, body) ordered by ID
+pub async fn get_unindexed_posts(
+	after: u64,
+	limit: u32,
+) -> DynResult<Vec<(u64, Node)>> {
+	let _timer = Timer::new("get_unindexed_posts");
+	sqlx::query!(
+		"select id, body
+		from posts
+		where id > $1 and not open and search is null
+		order by id
+		limit $2",
+		after as i64,
+		limit as i64,
+	)
+	.fetch_all(&pool())
+	.await?
+	.into_iter()
+	.map(|r| Ok((r.id as u64, serde_json::from_value(r.body)?)))
+	.collect()
+}
+
+/// Set the full-text search index of closed posts from their plain text
+/// as (id, text)
+pub async fn set_search_text(posts: &[(u64, String)]) -> DynResult {
+	let _timer = Timer::new("set_search_text");
+	let mut tx = pool().begin().await?;
+	for (id, text) in posts {
+		sqlx::query!(
+			"update posts
+			set search = to_tsvector('simple', $2)
+			where id = $1",
+			*id as i64,
+			text,
+		)
+		.execute(&mut tx)
+		.await?;
+	}
+	tx.commit().await?;
+	Ok(())
+}
+
+/// Return closed posts visible to everyone with bodies matching a web search
+/// engine syntax query, ordered by relevance.
+/// Optionally filtered by thread tag and thread.
+pub async fn search_posts(
+	query: &str,
+	tag: Option<&str>,
+	thread: Option<u64>,
+	limit: u32,
+	offset: u32,
+) -> DynResult<Vec<Post>> {
+	let _timer = Timer::new("search_posts");
+	Ok(serde_json::from_value(
+		sqlx::query!(
+			"select coalesce(
+				jsonb_agg(encode(p) order by m.rank desc, p.id desc),
+				'[]'
+			) posts
+			from (
+				select p.id, ts_rank(p.search, q) rank
+				from posts p
+				join threads t on t.id = p.thread,
+					websearch_to_tsquery('simple', $1) q
+				where p.search @@ q
+					and not p.deleted
+					and not p.shadow_binned
+					and ($2::text is null or $2 = any(t.tags))
+					and ($3::bigint is null or p.thread = $3)
+				order by rank desc, p.id desc
+				limit $4
+				offset $5
+			) m
+			join posts p on p.id = m.id",
+			query,
+			tag,
+			thread.map(|id| id as i64),
+			limit as i64,
+			offset as i64,
+		)
+		.fetch_one(&pool())
+		.await?
+		.posts
+		.ok_or("query returned no JSON")?,
+	)?)
+}
+
+/// Return the shadow binned posts on a page of a thread, optionally only those
+/// created with a public key.
+///
+/// Negative page numbers count from the end.
+pub async fn get_shadow_binned_posts(
+	thread: u64,
+	page: i32,
+	public_key: Option<u64>,
+) -> DynResult<Vec<Post>> {
+	let _timer = Timer::new("get_shadow_binned_posts");
+	Ok(serde_json::from_value(
+		sqlx::query!(
+			"select coalesce(jsonb_agg(encode(p) order by id), '[]') posts
+			from posts p
+			where shadow_binned
+				and thread = $1
+				and ($3::bigint is null or public_key = $3)
+				and page = case
+					when $2 >= 0 then $2
+					else (
+						select max(page) + 1
+						from posts
+						where thread = $1
+					) + $2
+				end",
+			thread as i64,
+			page,
+			public_key.map(|k| k as i64),
+		)
+		.fetch_one(&pool())
+		.await?
+		.posts
+		.ok_or("query returned no JSON")?,
+	)?)
+}
+
 /// Return the thread and page of a post, if any
 pub async fn get_post_parenthood(
 	id: u64,
 ) -> Result<Option<(u64, u32)>, sqlx::Error> {
+	let _timer = Timer::new("get_post_parenthood");
 	Ok(sqlx::query!(
 		"select thread, page
 		from posts
@@ -107,3 +278,76 @@ pub async fn get_post_parenthood(
 	.await?
 	.map(|r| (r.thread as u64, r.page as u32)))
 }
+
+/// Location, open status and public key of a post
+#[derive(Debug)]
+pub struct PostOwnership {
+	pub thread: u64,
+	pub page: u32,
+	pub open: bool,
+
+	/// Private ID of the public key the post was created with
+	pub public_key: Option<u64>,
+}
+
+/// Return the location, open status and public key of a post, if any
+pub async fn get_post_ownership(
+	id: u64,
+) -> Result<Option<PostOwnership>, sqlx::Error> {
+	let _timer = Timer::new("get_post_ownership");
+	Ok(sqlx::query!(
+		"select thread, page, open, public_key
+		from posts
+		where id = $1",
+		id as i64,
+	)
+	.fetch_optional(&pool())
+	.await?
+	.map(|r| PostOwnership {
+		thread: r.thread as u64,
+		page: r.page as u32,
+		open: r.open,
+		public_key: r.public_key.map(|k| k as u64),
+	}))
+}
+
+/// Open post with its creation time
+#[derive(Debug)]
+pub struct OpenPostDesc {
+	pub id: u64,
+	pub thread: u64,
+	pub page: u32,
+
+	/// Private ID of the public key the post was created with
+	pub public_key: Option<u64>,
+
+	/// Unix timestamp of post creation
+	pub created_on: u32,
+}
+
+/// Return all currently open posts
+pub async fn get_open_posts() -> DynResult<Vec<OpenPostDesc>> {
+	let _timer = Timer::new("get_open_posts");
+	Ok(sqlx::query!(
+		"select
+			id,
+			thread,
+			page,
+			public_key,
+			to_unix(created_on) created_on
+		from posts
+		where open = true
+		order by id",
+	)
+	.fetch_all(&pool())
+	.await?
+	.into_iter()
+	.map(|r| OpenPostDesc {
+		id: r.id as u64,
+		thread: r.thread as u64,
+		page: r.page as u32,
+		public_key: r.public_key.map(|k| k as u64),
+		created_on: r.created_on.unwrap_or_default() as u32,
+	})
+	.collect())
+}
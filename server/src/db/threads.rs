@@ -1,4 +1,4 @@
-use super::{pool, PostInsertParams};
+use super::{pool, PostInsertParams, Timer};
 use crate::util::DynResult;
 use common::payloads::{Post, ThreadWithPosts};
 use futures::StreamExt;
@@ -14,6 +14,7 @@ pub struct ThreadInsertParams<'a> {
 pub async fn insert_thread<'a>(
 	p: &mut ThreadInsertParams<'a>,
 ) -> DynResult<u64> {
+	let _timer = Timer::new("insert_thread");
 	let mut tx = pool().begin().await?;
 
 	let id: i64 = sqlx::query!(
@@ -35,7 +36,8 @@ pub async fn insert_thread<'a>(
 			name,
 			trip,
 			flag,
-			body
+			body,
+			ip
 		)
 		values (
 			$1,
@@ -44,7 +46,8 @@ pub async fn insert_thread<'a>(
 			$4,
 			$5,
 			$6,
-			$7
+			$7,
+			$8::text::inet
 		)",
 		id,
 		id,
@@ -53,6 +56,7 @@ pub async fn insert_thread<'a>(
 		p.op.trip,
 		p.op.flag,
 		serde_json::to_value(p.op.body)?,
+		p.op.ip.to_string(),
 	)
 	.execute(&mut tx)
 	.await?;
@@ -62,8 +66,114 @@ pub async fn insert_thread<'a>(
 	Ok(id as u64)
 }
 
+/// Flags of a thread affecting post insertion
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadFlags {
+	/// Thread does not accept any new replies
+	pub locked: bool,
+
+	/// Oldest replies are deleted past the configured post limit
+	pub cyclic: bool,
+}
+
+/// Return the post insertion flags of a thread, or None, if it does not exist
+pub async fn get_thread_flags(
+	id: u64,
+) -> Result<Option<ThreadFlags>, sqlx::Error> {
+	let _timer = Timer::new("get_thread_flags");
+	Ok(sqlx::query!(
+		"select locked, cyclic
+		from threads
+		where id = $1",
+		id as i64,
+	)
+	.fetch_optional(&pool())
+	.await?
+	.map(|r| ThreadFlags {
+		locked: r.locked,
+		cyclic: r.cyclic,
+	}))
+}
+
+/// Delete the oldest replies of a thread, that exceed the post limit
+/// including the OP, and return their IDs and pages
+pub async fn prune_thread(
+	id: u64,
+	limit: u32,
+) -> Result<Vec<(u64, u32)>, sqlx::Error> {
+	let _timer = Timer::new("prune_thread");
+	Ok(sqlx::query!(
+		"delete from posts
+		where id in (
+			select id
+			from posts
+			where thread = $1 and id != $1
+			order by id desc
+			offset $2
+		)
+		returning id, page",
+		id as i64,
+		limit.saturating_sub(1) as i64,
+	)
+	.fetch_all(&pool())
+	.await?
+	.into_iter()
+	.map(|r| (r.id as u64, r.page as u32))
+	.collect())
+}
+
+/// Delete all non-sticky threads not bumped for expiry days and return their
+/// IDs
+pub async fn delete_expired_threads(
+	expiry: u32,
+) -> Result<Vec<u64>, sqlx::Error> {
+	let _timer = Timer::new("delete_expired_threads");
+	Ok(sqlx::query!(
+		"delete from threads
+		where bumped_on < now() - $1 * interval '1 day' and not sticky
+		returning id",
+		expiry as f64,
+	)
+	.fetch_all(&pool())
+	.await?
+	.into_iter()
+	.map(|r| r.id as u64)
+	.collect())
+}
+
+/// Delete a thread and all of its posts.
+/// Returns false, if the thread did not exist.
+pub async fn delete_thread(id: u64) -> Result<bool, sqlx::Error> {
+	let _timer = Timer::new("delete_thread");
+	Ok(sqlx::query!(
+		"delete from threads
+		where id = $1",
+		id as i64,
+	)
+	.execute(&pool())
+	.await?
+	.rows_affected()
+		!= 0)
+}
+
+/// Listen for thread deletions in the DB and call on_delete with the ID of
+/// each deleted thread. Only returns on error.
+pub async fn listen_for_thread_deletions(
+	mut on_delete: impl FnMut(u64),
+) -> DynResult {
+	let mut l = sqlx::postgres::PgListener::connect_with(&pool()).await?;
+	l.listen("thread.deleted").await?;
+	loop {
+		match l.recv().await?.payload().parse() {
+			Ok(id) => on_delete(id),
+			Err(e) => log::error!("invalid thread deletion payload: {}", e),
+		}
+	}
+}
+
 /// Return all existing threads and their last 5 posts
 pub async fn get_all_threads_short() -> DynResult<Vec<ThreadWithPosts>> {
+	let _timer = Timer::new("get_all_threads_short");
 	let mut threads: Vec<ThreadWithPosts> = Vec::new();
 	let pool = pool();
 	let mut s = sqlx::query!("select get_thread(id, -5) thread from threads")
@@ -79,9 +189,10 @@ pub async fn get_all_threads_short() -> DynResult<Vec<ThreadWithPosts>> {
 
 /// Get a specific page of a thread
 pub async fn get_page(thread: u64, page: u32) -> DynResult<Vec<Post>> {
+	let _timer = Timer::new("get_page");
 	Ok(serde_json::from_value(
 		sqlx::query!(
-			"select jsonb_agg(encode(p) order by id) page
+			"select coalesce(jsonb_agg(encode(p) order by id), '[]') page
 			from posts p
 			where thread = $1 and page = $2",
 			thread as i64,
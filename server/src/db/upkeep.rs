@@ -0,0 +1,66 @@
+use super::{pool, Timer};
+use crate::util::DynResult;
+use common::payloads::FileType;
+
+/// Delete all expired rows from tables inheriting from expiries
+pub async fn delete_expired() -> DynResult {
+	let _timer = Timer::new("delete_expired");
+	sqlx::query!(
+		"delete from expiries
+		where expires < now()",
+	)
+	.execute(&pool())
+	.await?;
+	Ok(())
+}
+
+/// Image deleted from the DB, whose files need to be removed
+#[derive(Debug)]
+pub struct DeletedImage {
+	/// Hex-encoded SHA1 hash of the source file
+	pub sha1: String,
+
+	pub file_type: FileType,
+	pub thumb_type: FileType,
+}
+
+/// Delete all images not referenced by any post or pending image and older
+/// than an hour
+pub async fn delete_unused_images() -> DynResult<Vec<DeletedImage>> {
+	let _timer = Timer::new("delete_unused_images");
+	fn parse_type(t: Option<String>) -> DynResult<FileType> {
+		Ok(serde_json::from_value(serde_json::Value::String(
+			t.unwrap_or_default(),
+		))?)
+	}
+
+	sqlx::query!(
+		"delete from images i
+		where created_on < now() - interval '1 hour'
+			and not exists (
+				select
+				from posts p
+				where p.image = i.id
+			)
+			and not exists (
+				select
+				from pending_images pi
+				where pi.image = i.id
+			)
+		returning
+			encode(sha1, 'hex') sha1,
+			file_type::text file_type,
+			thumb_type::text thumb_type",
+	)
+	.fetch_all(&pool())
+	.await?
+	.into_iter()
+	.map(|r| {
+		Ok(DeletedImage {
+			sha1: r.sha1.unwrap_or_default(),
+			file_type: parse_type(r.file_type)?,
+			thumb_type: parse_type(r.thumb_type)?,
+		})
+	})
+	.collect()
+}
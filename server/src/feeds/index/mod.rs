@@ -5,19 +5,25 @@ use crate::{
 	client::{Client, SendMessage, SendMessageBatch},
 	message::Message,
 	message::Message as Msg,
+	metrics::{FeedMetrics, GetMetrics, Summary},
 	mt_context::{AsyncHandler, MTContext},
 	registry::Registry,
-	util::{self, DynResult, Pulse, WakeUp},
+	util::{self, DynResult, Flush, Pulse, WakeUp},
 };
 use actix::prelude::*;
 use async_trait::async_trait;
 use common::{
-	payloads::{post_body::Node, Post, ThreadWithPosts},
+	payloads::{
+		moderation::{ModerationAction, PostModerationEntry},
+		post_body::Node,
+		Post, Thread, ThreadWithPosts,
+	},
 	Encoder, MessageType,
 };
 use serde::Serialize;
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 use threads::Threads;
+use tokio::sync::oneshot;
 
 /// Change to be applied to thread data
 #[derive(Debug)]
@@ -28,6 +34,8 @@ pub enum Change {
 		body: Arc<Node>,
 		close_post: bool,
 	},
+	Moderate(PostModerationEntry),
+	PrunePosts(Vec<u64>),
 }
 
 /// Set of buffered changes for a particular thread
@@ -36,8 +44,8 @@ pub struct ChangeSet {
 	/// Source feed of the change
 	pub source_feed: u64,
 
-	/// Concatenated messages to be sent to clients
-	pub message: Message,
+	/// Concatenated messages to be sent to clients, if any
+	pub message: Option<Message>,
 
 	/// Unencoded contents of the messages
 	pub changes: Vec<Change>,
@@ -66,6 +74,9 @@ pub struct IndexFeed {
 
 	/// Fetches deferred to next pulse
 	deferred_fetches: Vec<Addr<Client>>,
+
+	/// Time spent processing buffered changes on each pulse
+	pulse_duration: Summary,
 }
 
 impl actix::Actor for IndexFeed {
@@ -81,6 +92,7 @@ impl AsyncHandler<Pulse> for IndexFeed {
 		_: Pulse,
 		_: &mut <Self as Actor>::Context,
 	) -> Result<(), Self::Error> {
+		let start = Instant::now();
 		self.pending_pulse = false;
 		self.clients = self
 			.registry
@@ -116,8 +128,10 @@ impl AsyncHandler<Pulse> for IndexFeed {
 					continue;
 				}
 			};
-			if !self.clients.is_empty() {
-				batch.push(cs.message);
+			if let Some(msg) = cs.message {
+				if !self.clients.is_empty() {
+					batch.push(msg);
+				}
 			}
 			for c in cs.changes {
 				use Change::*;
@@ -139,6 +153,26 @@ impl AsyncHandler<Pulse> for IndexFeed {
 							}
 						}
 					}
+					Moderate(PostModerationEntry { id, entry }) => {
+						if id == t.thread.id {
+							entry.action.apply_to_thread(&mut t.thread);
+						}
+						// Shadow binned posts are omitted from the index
+						if entry.action == ModerationAction::ShadowBinPost {
+							t.posts.remove(&id);
+						} else if let Some(p) = t.posts.get_mut(&id) {
+							p.moderate(entry);
+						}
+					}
+					PrunePosts(ids) => {
+						t.thread.post_count = t
+							.thread
+							.post_count
+							.saturating_sub(ids.len() as u64);
+						for id in ids {
+							t.posts.remove(&id);
+						}
+					}
 				};
 			}
 		}
@@ -150,10 +184,26 @@ impl AsyncHandler<Pulse> for IndexFeed {
 			}
 		}
 
+		self.pulse_duration.observe_duration(start.elapsed());
 		Ok(())
 	}
 }
 
+#[async_trait]
+impl AsyncHandler<Flush> for IndexFeed {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		req: Flush,
+		ctx: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		let res = AsyncHandler::<Pulse>::handle(self, Pulse, ctx).await;
+		req.done();
+		res
+	}
+}
+
 #[async_trait]
 impl AsyncHandler<WakeUp> for IndexFeed {
 	type Error = ();
@@ -178,8 +228,6 @@ impl AsyncHandler<super::InsertThread> for IndexFeed {
 		msg: super::InsertThread,
 		ctx: &mut <Self as Actor>::Context,
 	) -> Result<(), Self::Error> {
-		use common::payloads::{Post, Thread};
-
 		self.schedule_pulse(ctx);
 
 		let now = crate::util::now();
@@ -235,6 +283,31 @@ impl AsyncHandler<FetchFeedData> for IndexFeed {
 	}
 }
 
+/// Remove a deleted thread from the index.
+///
+/// Must be sent by the thread's feed after its last flush or, if the thread
+/// has no feed, by the registry, so that no changes from the thread's feed
+/// arrive after the removal.
+pub struct RemoveThread(pub u64);
+
+#[async_trait]
+impl AsyncHandler<RemoveThread> for IndexFeed {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		RemoveThread(id): RemoveThread,
+		ctx: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		self.changes.retain(|cs| cs.source_feed != id);
+		if self.threads.remove(&id).is_some() {
+			self.schedule_pulse(ctx);
+			self.write_message(MessageType::DeleteThread, &id)?;
+		}
+		Ok(())
+	}
+}
+
 /// Send set of used tags across all threads to client
 pub struct UsedTags(pub Addr<Client>);
 
@@ -252,6 +325,49 @@ impl AsyncHandler<UsedTags> for IndexFeed {
 	}
 }
 
+/// Read all threads in the index and their posts sorted by ID for rendering
+pub struct ReadThreads(pub oneshot::Sender<Vec<(Thread, Vec<Post>)>>);
+
+#[async_trait]
+impl AsyncHandler<ReadThreads> for IndexFeed {
+	type Error = ();
+
+	async fn handle(
+		&mut self,
+		ReadThreads(reply): ReadThreads,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		let threads = self
+			.threads
+			.values()
+			.map(|t| {
+				let mut posts = t.posts.values().cloned().collect::<Vec<_>>();
+				posts.sort_unstable_by_key(|p| p.id);
+				(t.thread.clone(), posts)
+			})
+			.collect();
+		std::mem::drop(reply.send(threads));
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl AsyncHandler<GetMetrics<FeedMetrics>> for IndexFeed {
+	type Error = ();
+
+	async fn handle(
+		&mut self,
+		req: GetMetrics<FeedMetrics>,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		req.reply(FeedMetrics {
+			pulse_duration: self.pulse_duration,
+			..Default::default()
+		});
+		Ok(())
+	}
+}
+
 impl IndexFeed {
 	pub fn new(
 		threads: Vec<ThreadWithPosts>,
@@ -264,12 +380,16 @@ impl IndexFeed {
 			threads: Threads::new(
 				threads
 					.into_iter()
-					.map(|t| (t.thread.id, t.into()))
+					.map(|mut t| {
+						t.posts.retain(|_, p| !p.shadow_binned);
+						(t.thread.id, t.into())
+					})
 					.collect(),
 			),
 			enc: Default::default(),
 			changes: Default::default(),
 			deferred_fetches: Default::default(),
+			pulse_duration: Default::default(),
 		}
 	}
 
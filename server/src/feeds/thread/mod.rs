@@ -9,21 +9,32 @@ use super::{
 use crate::{
 	body::persist_open::{BodyFlusher, PersistBodies},
 	client::{Client, Disconnect, SendMessage},
+	message::Message,
+	metrics::{FeedMetrics, GetMetrics, Summary},
 	mt_context::{AsyncHandler, MTAddr, MTContext},
-	registry::Registry,
-	util::{self, run_in_rayon, DynResult, MessageCacher, Pulse, WakeUp},
+	registry::{Registry, SendToStaff},
+	util::{
+		self, run_in_rayon, DynResult, Flush, MessageCacher, Pulse, WakeUp,
+	},
 };
 use actix::prelude::*;
 use async_trait::async_trait;
 use common::{
 	payloads::{
-		post_body::Node, ImmutablePage, Post, PostCreationNotification, Thread,
+		moderation::{ModerationAction, ModerationEntry, PostModerationEntry},
+		post_body::Node,
+		ImmutablePage, Post, PostCreationNotification, Thread,
 	},
-	MessageType,
+	Encoder, MessageType,
 };
 use page::{MutablePage, PageRecord};
 use rayon::prelude::*;
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+	collections::HashMap,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
 
 // TODO(?): if a feed does not have any clients and has not had activity for 5
 // minutes, request the registry to deallocate this thread. This only saves
@@ -74,6 +85,9 @@ pub struct ThreadFeed {
 
 	/// Pages currently loaded from the DB
 	pages: HashMap<u32, PageRecord>,
+
+	/// Time spent processing buffered changes on each pulse
+	pulse_duration: Summary,
 }
 
 impl actix::Actor for ThreadFeed {
@@ -96,6 +110,7 @@ impl AsyncHandler<Pulse> for ThreadFeed {
 		_: Pulse,
 		_: &mut <Self as Actor>::Context,
 	) -> Result<(), Self::Error> {
+		let start = Instant::now();
 		self.pending_pulse = false;
 
 		// Send any deferred page fetches first to maintain chronology.
@@ -124,10 +139,26 @@ impl AsyncHandler<Pulse> for ThreadFeed {
 			.await?;
 		self.writer.flush(self.clients.values())?;
 
+		self.pulse_duration.observe_duration(start.elapsed());
 		Ok(())
 	}
 }
 
+#[async_trait]
+impl AsyncHandler<Flush> for ThreadFeed {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		req: Flush,
+		ctx: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		let res = AsyncHandler::<Pulse>::handle(self, Pulse, ctx).await;
+		req.done();
+		res
+	}
+}
+
 #[async_trait]
 impl AsyncHandler<WakeUp> for ThreadFeed {
 	type Error = ();
@@ -222,6 +253,137 @@ impl AsyncHandler<FetchPage> for ThreadFeed {
 	}
 }
 
+/// Request to read the thread's metainformation
+pub struct ReadMeta(pub oneshot::Sender<Thread>);
+
+#[async_trait]
+impl AsyncHandler<ReadMeta> for ThreadFeed {
+	type Error = ();
+
+	async fn handle(
+		&mut self,
+		ReadMeta(reply): ReadMeta,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		std::mem::drop(reply.send((*self.thread_meta).clone()));
+		Ok(())
+	}
+}
+
+/// Request to read a page of the thread
+pub struct ReadPage {
+	/// Page ID to read
+	pub page: u32,
+
+	/// Receives the thread metainformation and the page's posts sorted by ID
+	/// with the OP prepended, or None, if the page does not exist
+	pub reply: oneshot::Sender<Option<(Thread, Vec<Post>)>>,
+}
+
+#[async_trait]
+impl AsyncHandler<ReadPage> for ThreadFeed {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		ReadPage { page, reply }: ReadPage,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		let res = if page < self.thread_meta.page_count {
+			let mut posts = self.read_page(page).await?;
+			if page != 0 {
+				let id = self.thread_meta.id;
+				if let Some(op) =
+					self.read_page(0).await?.into_iter().find(|p| p.id == id)
+				{
+					posts.insert(0, op);
+				}
+			}
+			Some(((*self.thread_meta).clone(), posts))
+		} else {
+			None
+		};
+		std::mem::drop(reply.send(res));
+		Ok(())
+	}
+}
+
+/// Request to read a single post of the thread
+pub struct ReadPost {
+	/// Location of the post in the thread
+	pub loc: PostLocation,
+
+	/// Receives the post, or None, if it does not exist on the page
+	pub reply: oneshot::Sender<Option<Post>>,
+}
+
+#[async_trait]
+impl AsyncHandler<ReadPost> for ThreadFeed {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		ReadPost { loc, reply }: ReadPost,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		use PageRecord::*;
+
+		let res = if loc.page < self.thread_meta.page_count {
+			match self.pages.get(&loc.page) {
+				// Avoid copying the entire page
+				Some(Mutable(p)) => p.get(&loc.id).map(|p| p.as_ref().clone()),
+				_ => self
+					.read_page(loc.page)
+					.await?
+					.into_iter()
+					.find(|p| p.id == loc.id),
+			}
+		} else {
+			None
+		};
+		std::mem::drop(reply.send(res));
+		Ok(())
+	}
+}
+
+/// Request to read the latest body of an open post, including any changes not
+/// yet diffed
+pub struct ReadOpenBody {
+	pub loc: PostLocation,
+
+	/// Receives the body parsed as a closed post, or None, if the post is not
+	/// open
+	pub reply: oneshot::Sender<Option<Node>>,
+}
+
+#[async_trait]
+impl AsyncHandler<ReadOpenBody> for ThreadFeed {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		ReadOpenBody { loc, reply }: ReadOpenBody,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		let pending = self
+			.pending_open_bodies
+			.get(&loc.page)
+			.and_then(|p| p.get(&loc.id))
+			.map(|b| b.iter().collect::<String>());
+		let res = match pending {
+			Some(text) => Some(crate::body::parse(&text, false)),
+			None => self
+				.read_page(loc.page)
+				.await?
+				.into_iter()
+				.find(|p| p.id == loc.id && p.open)
+				.map(|p| p.body.as_ref().clone()),
+		};
+		std::mem::drop(reply.send(res));
+		Ok(())
+	}
+}
+
 #[async_trait]
 impl AsyncHandler<InsertPost> for ThreadFeed {
 	type Error = util::Err;
@@ -238,6 +400,7 @@ impl AsyncHandler<InsertPost> for ThreadFeed {
 		if req.page > self.thread_meta.page_count {
 			self.thread_meta.page_count = req.page;
 		}
+		self.thread_meta.post_count += 1;
 		let now = util::now();
 		if !req.opts.sage {
 			self.thread_meta.bumped_on = now;
@@ -340,7 +503,7 @@ impl AsyncHandler<SetBody> for ThreadFeed {
 /// Propagate post closure
 pub struct ClosePost {
 	pub loc: PostLocation,
-	pub body: Node,
+	pub body: Arc<Node>,
 }
 
 #[async_trait]
@@ -356,7 +519,7 @@ impl AsyncHandler<ClosePost> for ThreadFeed {
 
 		self.schedule_pulse(ctx);
 
-		let body = Arc::new(req.body);
+		let body = req.body;
 
 		match match self.pages.entry(req.loc.page).or_default() {
 			p @ Mutable(_) | p @ Immutable(_) => p,
@@ -407,8 +570,159 @@ impl AsyncHandler<ClosePost> for ThreadFeed {
 	}
 }
 
-/// Try to make any pages that can no longer change immutable by moving them
-/// to memory-mapped files
+/// Propagate a moderation action already applied to a post in the DB
+pub struct ModeratePost {
+	pub loc: PostLocation,
+	pub entry: ModerationEntry,
+}
+
+#[async_trait]
+impl AsyncHandler<ModeratePost> for ThreadFeed {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		ModeratePost { loc, entry }: ModeratePost,
+		ctx: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		use page::PageRecord::*;
+
+		self.schedule_pulse(ctx);
+
+		if loc.id == self.thread_meta.id {
+			entry.action.apply_to_thread(&mut self.thread_meta);
+		}
+		match self.pages.entry(loc.page).or_default() {
+			Mutable(p) => {
+				if let Some(p) = p.get_mut(&loc.id) {
+					p.moderate(entry.clone());
+				}
+			}
+			// Immutable pages can not be modified in place. Have the page
+			// refetched from the DB on next access instead.
+			p @ Immutable(_) => *p = Unfetched,
+			Unfetched => (),
+		};
+		// Purged posts are closed and can no longer be edited
+		if entry.action == ModerationAction::PurgePost {
+			if let Some(p) = self.pending_open_bodies.get_mut(&loc.page) {
+				p.remove(&loc.id);
+			}
+		}
+
+		let payload = PostModerationEntry { id: loc.id, entry };
+		if payload.entry.action == ModerationAction::ShadowBinPost {
+			// Only staff are notified. Everyone else is simply not sent the
+			// post again.
+			common::log_msg_out!(MessageType::Moderate, &payload);
+			self.registry.do_send(SendToStaff {
+				feeds: vec![self.thread_meta.id, 0],
+				message: Message::new(Encoder::encode(
+					MessageType::Moderate,
+					&payload,
+				)?),
+			});
+			self.writer
+				.write_post_change(loc.id, Change::Moderate(payload));
+		} else {
+			self.writer.write_post_message(
+				loc.id,
+				MessageType::Moderate,
+				&payload,
+				Change::Moderate(payload.clone()),
+			)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Propagate deletion of the oldest replies of a cyclic thread already
+/// applied in the DB
+pub struct PrunePosts(pub Vec<PostLocation>);
+
+#[async_trait]
+impl AsyncHandler<PrunePosts> for ThreadFeed {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		PrunePosts(posts): PrunePosts,
+		ctx: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		use page::PageRecord::*;
+
+		if posts.is_empty() {
+			return Ok(());
+		}
+		self.schedule_pulse(ctx);
+
+		for loc in posts.iter() {
+			match self.pages.entry(loc.page).or_default() {
+				Mutable(p) => {
+					p.remove(&loc.id);
+				}
+				// Immutable pages can not be modified in place. Have the page
+				// refetched from the DB on next access instead.
+				p @ Immutable(_) => *p = Unfetched,
+				Unfetched => (),
+			};
+			if let Some(p) = self.pending_open_bodies.get_mut(&loc.page) {
+				p.remove(&loc.id);
+			}
+		}
+
+		let ids = posts.into_iter().map(|loc| loc.id).collect::<Vec<_>>();
+		crate::body::forget_post_locations(ids.iter().copied());
+		self.thread_meta.post_count =
+			self.thread_meta.post_count.saturating_sub(ids.len() as u64);
+		self.writer.write_message(MessageType::PrunePosts, &ids)?;
+		self.writer.write_message(
+			MessageType::ThreadMeta,
+			self.thread_meta.as_ref(),
+		)?;
+		self.writer.write_global_change(
+			MessageType::PrunePosts,
+			&ids,
+			Change::PrunePosts(ids.clone()),
+		)?;
+
+		Ok(())
+	}
+}
+
+/// Notify the feed's clients of the thread's deletion, remove the thread from
+/// the global feed, release all thread data and stop the feed
+pub struct StopFeed {
+	/// Last clients subscribed to the feed
+	pub clients: super::Clients,
+}
+
+#[async_trait]
+impl AsyncHandler<StopFeed> for ThreadFeed {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		StopFeed { clients }: StopFeed,
+		ctx: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		self.writer
+			.write_message(MessageType::DeleteThread, &self.thread_meta.id)?;
+		self.writer.flush(clients.values())?;
+		self.writer.remove_from_global_feed();
+
+		self.pages.clear();
+		self.pending_open_bodies.clear();
+		self.deferred_page_fetches.clear();
+		ctx.stop();
+
+		Ok(())
+	}
+}
+
+/// Try to make any pages that can no longer change immutable by encoding them
+/// once into shared reference-counted buffers
 #[derive(Clone)]
 struct TryMakePagesImmutable;
 
@@ -430,15 +744,14 @@ impl AsyncHandler<TryMakePagesImmutable> for ThreadFeed {
 					std::mem::swap(&mut old, rec);
 					match old {
 						PageRecord::Mutable(mut p) => {
-							*rec = PageRecord::new_immutable(&ImmutablePage {
+							*rec = PageRecord::new_immutable(ImmutablePage {
 								thread: self.thread_meta.id,
 								page: *id,
 								posts: p
 									.drain()
 									.map(|(_, p)| p.get())
 									.collect(),
-							})
-							.await?;
+							})?;
 						}
 						_ => unreachable!(),
 					}
@@ -465,6 +778,31 @@ impl AsyncHandler<FetchFeedData> for ThreadFeed {
 	}
 }
 
+#[async_trait]
+impl AsyncHandler<GetMetrics<FeedMetrics>> for ThreadFeed {
+	type Error = ();
+
+	async fn handle(
+		&mut self,
+		req: GetMetrics<FeedMetrics>,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		let mut m = FeedMetrics {
+			pulse_duration: self.pulse_duration,
+			..Default::default()
+		};
+		for p in self.pages.values() {
+			match p {
+				PageRecord::Unfetched => (),
+				PageRecord::Mutable(_) => m.mutable_pages += 1,
+				PageRecord::Immutable(_) => m.immutable_pages += 1,
+			}
+		}
+		req.reply(m);
+		Ok(())
+	}
+}
+
 impl ThreadFeed {
 	/// Create a new ThreadFeed.
 	///
@@ -491,6 +829,7 @@ impl ThreadFeed {
 			pending_open_bodies: Default::default(),
 			deferred_page_fetches: Default::default(),
 			pages: Default::default(),
+			pulse_duration: Default::default(),
 		};
 
 		for i in 0..=f.thread_meta.page_count {
@@ -517,17 +856,39 @@ impl ThreadFeed {
 		let posts = crate::db::get_page(thread, page).await?;
 		crate::body::cache_locations(posts.iter());
 		Ok(if PageRecord::can_be_made_immutable(posts.iter()) {
-			PageRecord::new_immutable(&ImmutablePage {
+			PageRecord::new_immutable(ImmutablePage {
 				thread,
 				page,
 				posts,
-			})
-			.await?
+			})?
 		} else {
 			PageRecord::new_mutable(posts)
 		})
 	}
 
+	/// Return copies of a page's posts sorted by ID, fetching the page from
+	/// the DB, if not loaded yet
+	async fn read_page(&mut self, page: u32) -> DynResult<Vec<Post>> {
+		use PageRecord::*;
+
+		let rec = self.pages.entry(page).or_default();
+		if matches!(rec, Unfetched) {
+			*rec = Self::fetch_page(self.thread_meta.id, page).await?;
+		}
+		let mut posts: Vec<Post> = match rec {
+			Unfetched => unreachable!(),
+			Mutable(p) => p.values().map(|p| p.as_ref().clone()).collect(),
+			// Immutable pages are only kept encoded
+			Immutable(msg) => {
+				common::Decoder::new(msg.as_ref())?
+					.read_next::<ImmutablePage>()?
+					.posts
+			}
+		};
+		posts.sort_unstable_by_key(|p| p.id);
+		Ok(posts)
+	}
+
 	/// Diff pending open post body changes in parallel and write messages to
 	/// encoders
 	async fn diff_open_bodies(&mut self) -> DynResult {
@@ -540,7 +901,7 @@ impl ThreadFeed {
 		async fn process(
 			page: &mut MutablePage,
 			pending: HashMap<u64, Vec<char>>,
-			mutation_batch: &mut Vec<(u64, Patch, Arc<Node>)>,
+			mutation_batch: &mut Vec<(u64, Patch, Arc<Node>, bool)>,
 		) -> DynResult {
 			let to_diff = pending
 				.into_iter()
@@ -571,14 +932,15 @@ impl ThreadFeed {
 			.await?
 			.into_iter()
 			{
-				page.get_mut(&id).unwrap().body = body.clone();
-				mutation_batch.push((id, patch, body));
+				let p = page.get_mut(&id).unwrap();
+				p.body = body.clone();
+				mutation_batch.push((id, patch, body, p.shadow_binned));
 			}
 
 			Ok(())
 		}
 
-		let mut mutation_batch = Vec::<(u64, Patch, Arc<Node>)>::new();
+		let mut mutation_batch = Vec::<(u64, Patch, Arc<Node>, bool)>::new();
 		for (page_id, pending) in std::mem::take(&mut self.pending_open_bodies)
 		{
 			use PageRecord::*;
@@ -602,17 +964,20 @@ impl ThreadFeed {
 			let req = PersistBodies(
 				mutation_batch
 					.into_iter()
-					.map(|(id, patch, body)| {
-						self.writer.write_post_message(
-							id,
-							MessageType::PatchPostBody,
-							&PostBodyPatch { id, patch },
-							Change::SetBody {
+					.map(|(id, patch, body, shadow_binned)| {
+						// Shadow binned posts are not sent to clients by feeds
+						if !shadow_binned {
+							self.writer.write_post_message(
 								id,
-								body: body.clone(),
-								close_post: false,
-							},
-						)?;
+								MessageType::PatchPostBody,
+								&PostBodyPatch { id, patch },
+								Change::SetBody {
+									id,
+									body: body.clone(),
+									close_post: false,
+								},
+							)?;
+						}
 						Ok((id, body))
 					})
 					.collect::<DynResult<Vec<_>>>()?,
@@ -26,7 +26,8 @@ impl MutablePage {
 		Self { posts, cache: None }
 	}
 
-	/// Retrieve a cached message or generate a new one
+	/// Retrieve a cached message or generate a new one.
+	/// Shadow binned posts are omitted.
 	pub fn get_message(&mut self) -> std::io::Result<Message> {
 		macro_rules! static_encode {
 			($name:ident, $type:ident) => {
@@ -53,6 +54,7 @@ impl MutablePage {
 				parts.extend(
 					self.posts
 						.par_iter_mut()
+						.filter(|(_, p)| !p.shadow_binned)
 						.map(|(_, p)| p.get_message(MessageType::Post))
 						.collect::<std::io::Result<Vec<_>>>()?,
 				);
@@ -118,24 +120,15 @@ impl PageRecord {
 		))
 	}
 
-	/// Construct new immutable PageRecord
-	pub async fn new_immutable(page: &ImmutablePage) -> DynResult<Self> {
-		let buf = Encoder::encode(MessageType::Page, page)?;
-		let m = Box::new(
-			actix_web::web::block(move || -> DynResult<memmap::Mmap> {
-				let mut m =
-					memmap::MmapOptions::new().len(buf.len()).map_anon()?;
-				m.clone_from_slice(&buf);
-				Ok(m.make_read_only()?)
-			})
-			.await??,
-		);
-
-		// XXX: This leaks virtual memory, if the thread is deleted.
-		// To not leak the mmap you'd need to extend Bytes with construction
-		// from an owned AsRef<[u8]> (AsRef<[u8]> + Drop ?) or just MMap,
-		// and have it drop the memory map, when Bytes is dropped.
-		Ok(Self::Immutable(Bytes::from_static(Box::leak(m)).into()))
+	/// Construct new immutable PageRecord. Shadow binned posts are omitted.
+	///
+	/// The encoded page is reference counted and freed, once the record and
+	/// all messages sent to clients are dropped.
+	pub fn new_immutable(mut page: ImmutablePage) -> DynResult<Self> {
+		page.posts.retain(|p| !p.shadow_binned);
+		Ok(Self::Immutable(
+			Bytes::from(Encoder::encode(MessageType::Page, &page)?).into(),
+		))
 	}
 
 	/// Returns if a page can be considered immutable
@@ -1,5 +1,5 @@
 use super::{
-	super::index::{Change, ChangeSet, IndexFeed},
+	super::index::{Change, ChangeSet, IndexFeed, RemoveThread},
 	last_5::Last5,
 };
 use crate::{
@@ -12,8 +12,8 @@ use serde::Serialize;
 /// Pending messages and changes to be sent to the global thread index feed
 #[derive(Default, Debug)]
 struct Global {
-	/// Messages to be encoded and sent
-	enc: Encoder,
+	/// Messages to be encoded and sent, if any
+	enc: Option<Encoder>,
 
 	/// Changes reflected by the messages
 	changes: Vec<Change>,
@@ -84,12 +84,26 @@ impl Writer {
 		T: Serialize + std::fmt::Debug,
 	{
 		self.write_message(t, payload)?;
-		if post_id == self.feed || post_id >= self.last_5_posts.min() {
+		if self.in_global_feed(post_id) {
 			self.write_global_change(t, payload, change)?;
 		}
 		Ok(())
 	}
 
+	/// Propagate a post-related change to the global feed without sending any
+	/// messages to clients, if needed
+	pub fn write_post_change(&mut self, post_id: u64, change: Change) {
+		if self.in_global_feed(post_id) {
+			self.global().changes.push(change);
+		}
+	}
+
+	/// Returns, if a post is included in the global thread index feed
+	#[inline]
+	fn in_global_feed(&self, post_id: u64) -> bool {
+		post_id == self.feed || post_id >= self.last_5_posts.min()
+	}
+
 	/// Write message to pending message encoder
 	pub fn write_message<T>(&mut self, t: MessageType, payload: &T) -> DynResult
 	where
@@ -109,18 +123,19 @@ impl Writer {
 		payload: &impl Serialize,
 		change: Change,
 	) -> DynResult {
-		let set = match &mut self.global {
-			Some(s) => s,
-			None => {
-				self.global = Some(Default::default());
-				self.global.as_mut().unwrap()
-			}
-		};
-		set.enc.write_message(t, payload)?;
+		let set = self.global();
+		set.enc
+			.get_or_insert_with(|| Default::default())
+			.write_message(t, payload)?;
 		set.changes.push(change);
 		Ok(())
 	}
 
+	/// Return the pending global feed changeset, creating it, if none
+	fn global(&mut self) -> &mut Global {
+		self.global.get_or_insert_with(|| Default::default())
+	}
+
 	/// Flush changes and send them to all clients and the global feed
 	pub fn flush<'a>(
 		&mut self,
@@ -137,10 +152,19 @@ impl Writer {
 		if let Some(set) = self.global.take() {
 			self.index_feed.do_send(ChangeSet {
 				source_feed: self.feed,
-				message: Message::new(set.enc.finish()?),
+				message: match set.enc {
+					Some(enc) => Some(Message::new(enc.finish()?)),
+					None => None,
+				},
 				changes: set.changes,
 			})
 		}
 		Ok(())
 	}
+
+	/// Remove the thread from the global feed. Must be called after the last
+	/// flush, so no further changes from this feed reach the global feed.
+	pub fn remove_from_global_feed(&self) {
+		self.index_feed.do_send(RemoveThread(self.feed));
+	}
 }
@@ -0,0 +1,281 @@
+use super::{body, origin, post::format_rfc3339};
+use crate::{
+	feeds::{IndexFeed, ReadMeta, ReadPage, ReadThreads},
+	mt_context::MTAddr,
+	registry::{self, Registry},
+	util,
+};
+use actix::prelude::*;
+use actix_web::{
+	error::{ErrorInternalServerError, ErrorNotFound},
+	get, web, HttpRequest, HttpResponse, Result, Scope,
+};
+use common::payloads::{Post, Thread};
+use htmlescape::encode_minimal as escape;
+use std::fmt::Write;
+use tokio::sync::oneshot;
+
+/// Maximum number of entries in a feed
+const MAX_ENTRIES: usize = 50;
+
+/// Atom feeds of new threads and thread replies
+pub fn scope() -> Scope {
+	web::scope("/feeds")
+		.service(get_index)
+		.service(get_tag)
+		.service(get_thread)
+}
+
+/// Feed entry linking to a post
+struct Entry<'a> {
+	title: String,
+	post: &'a Post,
+}
+
+/// Render an Atom feed document. `feed_path` is the path of the feed itself
+/// and `page_path` is the path of the page the feed mirrors.
+fn render(
+	origin: &str,
+	feed_path: &str,
+	page_path: &str,
+	title: &str,
+	entries: &[Entry],
+) -> std::result::Result<String, std::fmt::Error> {
+	let mut w = String::new();
+	let feed_url = format!("{}{}", origin, feed_path);
+	writeln!(w, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+	writeln!(
+		w,
+		r#"<feed xmlns="http://www.w3.org/2005/Atom" xml:base="{}/">"#,
+		escape(origin)
+	)?;
+	// The feed's own URL is its permanent ID
+	writeln!(w, "<id>{}</id>", escape(&feed_url))?;
+	writeln!(w, "<title>{}</title>", escape(title))?;
+	writeln!(w, r#"<link rel="self" href="{}"/>"#, escape(&feed_url))?;
+	writeln!(
+		w,
+		r#"<link rel="alternate" href="{}{}"/>"#,
+		escape(origin),
+		escape(page_path)
+	)?;
+	writeln!(
+		w,
+		"<updated>{}</updated>",
+		format_rfc3339(
+			entries
+				.iter()
+				.map(|e| e.post.created_on)
+				.max()
+				.unwrap_or_else(util::now)
+		)
+	)?;
+
+	for e in entries {
+		let p = e.post;
+		// Same URL scheme as the client uses
+		let url =
+			format!("{}/threads/{}/{}#p-{}", origin, p.thread, p.page, p.id);
+		let author = match (&p.name, &p.trip) {
+			(None, None) => "Anonymous".into(),
+			(Some(name), None) => name.clone(),
+			(None, Some(trip)) => format!("!{}", trip),
+			(Some(name), Some(trip)) => format!("{} !{}", name, trip),
+		};
+
+		writeln!(w, "<entry>")?;
+		writeln!(w, "<id>{}</id>", escape(&url))?;
+		writeln!(w, "<title>{}</title>", escape(&e.title))?;
+		writeln!(w, r#"<link rel="alternate" href="{}"/>"#, escape(&url))?;
+		writeln!(w, "<updated>{}</updated>", format_rfc3339(p.created_on))?;
+		writeln!(w, "<author><name>{}</name></author>", escape(&author))?;
+		writeln!(
+			w,
+			r#"<content type="html">{}</content>"#,
+			escape(&body::render(&p.body).into_string())
+		)?;
+		writeln!(w, "</entry>")?;
+	}
+
+	writeln!(w, "</feed>")?;
+	Ok(w)
+}
+
+/// Encode a feed document response
+fn respond(
+	req: &HttpRequest,
+	feed_path: &str,
+	page_path: &str,
+	title: &str,
+	entries: &[Entry],
+) -> Result<HttpResponse> {
+	Ok(util::respond_with_etag(
+		req,
+		"application/atom+xml; charset=utf-8",
+		render(origin(), feed_path, page_path, title, entries)
+			.map_err(ErrorInternalServerError)?
+			.into_bytes(),
+	))
+}
+
+/// Only closed posts visible to everyone are included in feeds
+fn is_included(p: &Post) -> bool {
+	!p.open && !p.deleted && !p.shadow_binned
+}
+
+/// Render a feed of the newest threads on the thread index, optionally
+/// filtered by tag
+async fn render_threads(
+	req: &HttpRequest,
+	index_feed: &MTAddr<IndexFeed>,
+	tag: Option<&str>,
+) -> Result<HttpResponse> {
+	let (send, receive) = oneshot::channel();
+	index_feed.do_send(ReadThreads(send));
+	let threads = receive.await.map_err(ErrorInternalServerError)?;
+
+	let mut entries = threads
+		.iter()
+		.filter(|(t, _)| match tag {
+			Some(tag) => t.tags.iter().any(|t| t == tag),
+			None => true,
+		})
+		.filter_map(|(t, posts)| {
+			posts
+				.iter()
+				.find(|p| p.id == t.id && is_included(p))
+				.map(|op| Entry {
+					title: t.subject.clone(),
+					post: op,
+				})
+		})
+		.collect::<Vec<_>>();
+	entries.sort_unstable_by_key(|e| std::cmp::Reverse(e.post.id));
+	entries.truncate(MAX_ENTRIES);
+
+	match tag {
+		Some(tag) => respond(
+			req,
+			&format!("/feeds/tags/{}.atom", tag),
+			"/",
+			&format!("/{}/ - shamichan", tag),
+			&entries,
+		),
+		None => respond(req, "/feeds/index.atom", "/", "shamichan", &entries),
+	}
+}
+
+/// Newest threads
+#[get("/index.atom")]
+async fn get_index(
+	req: HttpRequest,
+	index_feed: web::Data<MTAddr<IndexFeed>>,
+) -> Result<HttpResponse> {
+	render_threads(&req, &index_feed, None).await
+}
+
+/// Newest threads with a tag
+#[get("/tags/{tag}.atom")]
+async fn get_tag(
+	req: HttpRequest,
+	tag: web::Path<String>,
+	index_feed: web::Data<MTAddr<IndexFeed>>,
+) -> Result<HttpResponse> {
+	render_threads(&req, &index_feed, Some(tag.as_str())).await
+}
+
+/// Newest replies in a thread
+#[get("/threads/{id:\\d+}.atom")]
+async fn get_thread(
+	req: HttpRequest,
+	id: web::Path<u64>,
+	registry: web::Data<Addr<Registry>>,
+) -> Result<HttpResponse> {
+	let feed = registry
+		.send(registry::GetFeed(id.into_inner()))
+		.await
+		.map_err(ErrorInternalServerError)?
+		.map_err(ErrorNotFound)?;
+
+	let (send, receive) = oneshot::channel();
+	feed.do_send(ReadMeta(send));
+	let thread: Thread = receive.await.map_err(ErrorInternalServerError)?;
+
+	// Read pages from the end, until there are enough entries
+	let mut posts = Vec::new();
+	for page in (0..thread.page_count).rev() {
+		let (send, receive) = oneshot::channel();
+		feed.do_send(ReadPage { page, reply: send });
+		if let Some((_, page_posts)) =
+			receive.await.map_err(ErrorInternalServerError)?
+		{
+			posts.extend(
+				page_posts
+					.into_iter()
+					.filter(|p| p.page == page && is_included(p)),
+			);
+		}
+		if posts.len() >= MAX_ENTRIES {
+			break;
+		}
+	}
+	posts.sort_unstable_by_key(|p| std::cmp::Reverse(p.id));
+	posts.truncate(MAX_ENTRIES);
+
+	respond(
+		&req,
+		&format!("/feeds/threads/{}.atom", thread.id),
+		&format!("/threads/{}/{}", thread.id, thread.page_count - 1),
+		&thread.subject,
+		&posts
+			.iter()
+			.map(|p| Entry {
+				title: format!(">>{}", p.id),
+				post: p,
+			})
+			.collect::<Vec<_>>(),
+	)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use common::payloads::{post_body::Node, PostCreationOpts};
+	use std::sync::Arc;
+
+	#[test]
+	fn render_feed() {
+		let mut p = Post::new_op(
+			1,
+			951827696,
+			PostCreationOpts {
+				name: Some("<a>".into()),
+				..Default::default()
+			},
+		);
+		p.open = false;
+		p.body = Arc::new(Node::Bold(Box::new(Node::Text("&".into()))));
+
+		let feed = render(
+			"https://example.com",
+			"/feeds/index.atom",
+			"/",
+			"shamichan",
+			&[Entry {
+				title: "subject".into(),
+				post: &p,
+			}],
+		)
+		.unwrap();
+		for s in &[
+			"<id>https://example.com/feeds/index.atom</id>",
+			r#"<link rel="alternate" href="https://example.com/"/>"#,
+			"<updated>2000-02-29T12:34:56Z</updated>",
+			"<id>https://example.com/threads/1/0#p-1</id>",
+			"<author><name>&lt;a&gt;</name></author>",
+			r#"<content type="html">&lt;b&gt;&amp;amp;&lt;/b&gt;</content>"#,
+		] {
+			assert!(feed.contains(s), "{} not in {}", s, feed);
+		}
+	}
+}
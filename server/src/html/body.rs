@@ -0,0 +1,167 @@
+use common::payloads::post_body::{Command, Node};
+use maud::{html, Markup, PreEscaped};
+
+/// Render a post body node tree to the same markup the client produces
+pub fn render(n: &Node) -> Markup {
+	use Node::*;
+
+	match n {
+		Empty => html! {},
+		Text(s) => html! { (s) },
+		Newline => html! { br; },
+		Children(v) => html! {
+			@for n in v {
+				(render(n))
+			}
+		},
+		PostLink { id, thread, page } => render_post_link(*id, *thread, *page),
+		Command(comm) => render_command(comm),
+		URL(u) => html! {
+			a href=(u) target="_blank" { (u) }
+		},
+		Reference { label, url } => html! {
+			a href=(url) target="_blank" { ">>>/" (label) "/" }
+		},
+		// Embeds are expanded by the client
+		Embed { url, .. } => html! {
+			a href=(url) target="_blank" { (url) }
+		},
+		// Highlighted server-side on post closure
+		Code(code) => html! {
+			div { (PreEscaped(code)) }
+		},
+		Spoiler(ch) => html! {
+			del { (render(ch)) }
+		},
+		Quoted(ch) => html! {
+			em { (render(ch)) }
+		},
+		Bold(ch) => html! {
+			b { (render(ch)) }
+		},
+		Italic(ch) => html! {
+			i { (render(ch)) }
+		},
+		Pending(n) => html! {
+			strong.pending { (n.format()) }
+		},
+	}
+}
+
+/// Render a post body node tree as plain text for use in link previews.
+/// Spoilers and code are omitted.
+pub fn render_text(n: &Node, w: &mut String) {
+	use Node::*;
+
+	match n {
+		Empty | Spoiler(_) | Code(_) => (),
+		Text(s) | URL(s) | Embed { url: s, .. } => w.push_str(s),
+		Newline => w.push(' '),
+		Children(v) => {
+			for n in v {
+				render_text(n, w);
+			}
+		}
+		PostLink { id, .. } => *w += &format!(">>{}", id),
+		Command(comm) => *w += &comm.format().0,
+		Reference { label, .. } => *w += &format!(">>>/{}/", label),
+		Quoted(ch) | Bold(ch) | Italic(ch) => render_text(ch, w),
+		Pending(n) => *w += &n.format(),
+	}
+}
+
+fn render_post_link(id: u64, thread: u64, page: u32) -> Markup {
+	// If thread = 0, link has not had it's parenthood looked up yet
+	let href = if thread != 0 {
+		Some(format!("/threads/{}/{}#p-{}", thread, page, id))
+	} else {
+		None
+	};
+	html! {
+		a href=[href] { ">>" (id) }
+	}
+}
+
+fn render_command(comm: &Command) -> Markup {
+	let (text, class) = comm.format();
+	if class.is_empty() {
+		html! {
+			strong { (text) }
+		}
+	} else {
+		html! {
+			strong class=(class) { (text) }
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use common::payloads::post_body::PendingNode;
+	use Node::{
+		Bold, Children, Newline, Pending, PostLink, Quoted, Spoiler, Text,
+	};
+
+	fn assert_renders(n: Node, expected: &str) {
+		assert_eq!(render(&n).into_string(), expected);
+	}
+
+	#[test]
+	fn escapes_text() {
+		assert_renders(
+			Children(vec![Text("<b>&</b>".into()), Newline]),
+			"&lt;b&gt;&amp;&lt;/b&gt;<br>",
+		);
+	}
+
+	#[test]
+	fn post_links() {
+		assert_renders(
+			Children(vec![
+				PostLink {
+					id: 3,
+					thread: 1,
+					page: 0,
+				},
+				Pending(PendingNode::PostLink(4)),
+			]),
+			"<a href=\"/threads/1/0#p-3\">&gt;&gt;3</a>\
+			<strong class=\"pending\">&gt;&gt;4</strong>",
+		);
+	}
+
+	#[test]
+	fn formatting() {
+		assert_renders(
+			Quoted(Box::new(Spoiler(Box::new(Bold(Box::new(Text(
+				"a".into(),
+			))))))),
+			"<em><del><b>a</b></del></em>",
+		);
+	}
+
+	#[test]
+	fn plain_text() {
+		let mut w = String::new();
+		render_text(
+			&Children(vec![
+				Text("a".into()),
+				Newline,
+				Quoted(Box::new(Text(">b".into()))),
+				Spoiler(Box::new(Text("c".into()))),
+				Pending(PendingNode::PostLink(4)),
+			]),
+			&mut w,
+		);
+		assert_eq!(w, "a >b>>4");
+	}
+
+	#[test]
+	fn commands() {
+		assert_renders(
+			Node::Command(Command::Flip(true)),
+			"<strong>#flip (flap)</strong>",
+		);
+	}
+}
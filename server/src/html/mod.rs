@@ -0,0 +1,188 @@
+pub mod atom;
+mod body;
+mod post;
+
+use crate::{
+	config,
+	feeds::{IndexFeed, ReadPage, ReadThreads},
+	mt_context::MTAddr,
+	registry::{self, Registry},
+	util,
+};
+use actix::prelude::*;
+use actix_web::{
+	error::{ErrorInternalServerError, ErrorNotFound},
+	get, web, HttpRequest, HttpResponse,
+};
+use askama::Template;
+use common::{
+	config::Public,
+	payloads::{FileType, Post, Thread},
+};
+use maud::Markup;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+
+#[derive(Template)]
+#[template(path = "index.html")]
+struct Index<'a> {
+	config: Arc<Public>,
+
+	/// Page title
+	title: &'a str,
+
+	/// Server-side rendered content. The client does not hydrate it, but
+	/// removes it on start and renders the application in its place.
+	/// See main_js() in the client for the limitation.
+	content: String,
+
+	/// Link preview metadata
+	meta: Option<Meta>,
+}
+
+impl Index<'_> {
+	/// Return the fingerprinted URL of a static asset
+	fn asset(&self, path: &str) -> String {
+		crate::assets::url(path)
+	}
+
+	/// Return the fingerprinted URL of the default theme's stylesheet
+	fn theme_css(&self) -> String {
+		self.asset(&format!("css/{}.css", self.config.default_theme))
+	}
+}
+
+/// OpenGraph and Twitter card metadata for link previews
+struct Meta {
+	title: String,
+	description: String,
+
+	/// Absolute URL of the preview image, if any
+	image: Option<String>,
+}
+
+impl Meta {
+	/// Maximum length of the description in characters
+	const MAX_DESCRIPTION_LEN: usize = 200;
+
+	/// Build link preview metadata for a thread from its OP
+	fn new(thread: &Thread, op: &Post) -> Self {
+		let mut description = String::new();
+		body::render_text(&op.body, &mut description);
+		if description.chars().count() > Self::MAX_DESCRIPTION_LEN {
+			description = description
+				.chars()
+				.take(Self::MAX_DESCRIPTION_LEN - 1)
+				.chain(std::iter::once('…'))
+				.collect();
+		}
+
+		Self {
+			title: thread.subject.clone(),
+			description,
+			image: op
+				.image
+				.as_ref()
+				// No thumbnail or hidden behind a spoiler
+				.filter(|img| {
+					img.thumb_type != FileType::NoFile && !img.spoilered
+				})
+				.map(|img| origin().to_owned() + &post::thumb_path(img)),
+		}
+	}
+}
+
+/// Return the configured public scheme and host of the server.
+/// Request headers are not used, as clients control them.
+fn origin() -> &'static str {
+	config::SERVER.public_origin.trim_end_matches('/')
+}
+
+/// Render the client application HTML page with optional server-side rendered
+/// content
+fn respond(
+	req: &HttpRequest,
+	title: &str,
+	content: Option<Markup>,
+	meta: Option<Meta>,
+) -> actix_web::Result<HttpResponse> {
+	let page = Index {
+		config: config::get().public.clone(),
+		title,
+		content: content.map(Markup::into_string).unwrap_or_default(),
+		meta,
+	}
+	.render()
+	.map_err(ErrorInternalServerError)?;
+
+	Ok(util::respond_with_etag(
+		req,
+		"text/html; charset=utf-8",
+		page.into_bytes(),
+	))
+}
+
+/// Render the thread index
+pub async fn index(
+	req: HttpRequest,
+	index_feed: web::Data<MTAddr<IndexFeed>>,
+) -> actix_web::Result<HttpResponse> {
+	let (send, receive) = oneshot::channel();
+	index_feed.do_send(ReadThreads(send));
+	let mut threads = receive.await.map_err(ErrorInternalServerError)?;
+
+	respond(
+		&req,
+		"shamichan",
+		Some(post::render_index(&mut threads)),
+		None,
+	)
+}
+
+/// Render the thread catalog. Only the client application is served for now.
+pub async fn catalog(req: HttpRequest) -> actix_web::Result<HttpResponse> {
+	respond(&req, "shamichan", None, None)
+}
+
+/// Render a page of a thread
+pub async fn thread(
+	req: HttpRequest,
+	path: web::Path<(u64, u32)>,
+	registry: web::Data<Addr<Registry>>,
+) -> actix_web::Result<HttpResponse> {
+	let (id, page) = path.into_inner();
+	let feed = registry
+		.send(registry::GetFeed(id))
+		.await
+		.map_err(ErrorInternalServerError)?
+		.map_err(ErrorNotFound)?;
+
+	let (send, receive) = oneshot::channel();
+	feed.do_send(ReadPage { page, reply: send });
+	let (thread, posts) = receive
+		.await
+		.map_err(ErrorInternalServerError)?
+		.ok_or_else(|| ErrorNotFound(format!("page not found: {}", page)))?;
+
+	respond(
+		&req,
+		&format!("{} - shamichan", thread.subject),
+		Some(post::render_thread(&thread, page, &posts)),
+		posts
+			.iter()
+			.find(|p| p.id == thread.id)
+			.map(|op| Meta::new(&thread, op)),
+	)
+}
+
+/// Instruct bots to not access the site, if configured, and the API otherwise
+#[get("/robots.txt")]
+pub async fn robots_txt() -> HttpResponse {
+	HttpResponse::Ok()
+		.content_type("text/plain; charset=utf-8")
+		.body(if config::get().disable_robots {
+			"User-agent: *\nDisallow: /\n"
+		} else {
+			"User-agent: *\nDisallow: /api/\n"
+		})
+}
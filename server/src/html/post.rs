@@ -0,0 +1,327 @@
+use super::body;
+use common::payloads::{FileType, Image, Post, Thread};
+use maud::{html, Markup};
+
+/// Number of latest replies rendered for each thread on the thread index
+const INDEX_REPLIES: usize = 5;
+
+/// Render the threads of the thread index with their OPs and latest replies
+pub fn render_index(threads: &mut [(Thread, Vec<Post>)]) -> Markup {
+	// TODO: Different sort orders
+	threads.sort_unstable_by_key(|(t, _)| {
+		std::cmp::Reverse((t.sticky, t.bumped_on))
+	});
+
+	html! {
+		section {
+			@for (i, (t, posts)) in threads.iter().enumerate() {
+				@if i != 0 {
+					hr;
+				}
+				(render_index_thread(t, posts))
+			}
+		}
+	}
+}
+
+/// Render a page of a thread. `posts` must be sorted by ID.
+pub fn render_thread(t: &Thread, page: u32, posts: &[Post]) -> Markup {
+	html! {
+		(render_thread_posts(
+			t,
+			posts.iter().find(|p| p.id == t.id),
+			&posts.iter().filter(|p| p.id != t.id).collect::<Vec<_>>(),
+		))
+		@if t.page_count > 1 {
+			nav.spaced {
+				@for i in 0..t.page_count {
+					@if i == page {
+						b { (i) }
+					} @else {
+						a href=(format!("/threads/{}/{}", t.id, i)) { (i) }
+					}
+				}
+			}
+		}
+	}
+}
+
+/// Render the OP and latest replies of a thread on the thread index
+fn render_index_thread(t: &Thread, posts: &[Post]) -> Markup {
+	let replies = posts.iter().filter(|p| p.id != t.id).collect::<Vec<_>>();
+	render_thread_posts(
+		t,
+		posts.iter().find(|p| p.id == t.id),
+		&replies[replies.len().saturating_sub(INDEX_REPLIES)..],
+	)
+}
+
+fn render_thread_posts(
+	t: &Thread,
+	op: Option<&Post>,
+	replies: &[&Post],
+) -> Markup {
+	html! {
+		section.thread-container {
+			@if let Some(op) = op {
+				(render_post(op, Some(t)))
+			}
+			@for p in replies {
+				(render_post(p, None))
+			}
+		}
+	}
+}
+
+/// Render a post. `thread` must be set for OPs.
+fn render_post(p: &Post, thread: Option<&Thread>) -> Markup {
+	// Only visible to the author
+	if p.shadow_binned {
+		return html! {};
+	}
+
+	let mut cls = String::from("glass");
+	if p.open {
+		cls += " open";
+	}
+	if p.deleted {
+		cls += " deleted";
+	}
+
+	html! {
+		article class=(cls) id=(format!("p-{}", p.id)) {
+			header.spaced {
+				@if let Some(t) = thread {
+					@for tag in &t.tags {
+						b { "/" (tag) "/" }
+					}
+					h3 { "「" (t.subject) "」" }
+				}
+				(render_name(p))
+				@if let Some(flag) = &p.flag {
+					img.flag
+						src=(crate::assets::url(&format!("flags/{}.svg", flag)))
+						title=(flag);
+				}
+				(render_time(p.created_on))
+				nav.spaced {
+					a href=(format!(
+						"/threads/{}/{}#p-{}",
+						p.thread, p.page, p.id
+					)) {
+						"#"
+					}
+					a.quote { (p.id) }
+				}
+			}
+			@if let Some(img) = &p.image {
+				(render_figcaption(img))
+			}
+			div.post-container {
+				@if let Some(img) = &p.image {
+					(render_figure(img))
+				}
+				blockquote { (body::render(&p.body)) }
+			}
+		}
+	}
+}
+
+fn render_name(p: &Post) -> Markup {
+	html! {
+		b.name.sage[p.sage] {
+			@if p.name.is_none() && p.trip.is_none() {
+				// Localized by the client
+				span { "Anonymous" }
+			} @else {
+				@if let Some(name) = &p.name {
+					span { (name) }
+				}
+				@if let Some(trip) = &p.trip {
+					code { (trip) }
+				}
+			}
+		}
+	}
+}
+
+/// Render a Unix timestamp as an UTC date. Converted to local time by the
+/// client.
+fn render_time(secs: u32) -> Markup {
+	let (y, m, d) = civil_from_days(secs as i64 / (60 * 60 * 24));
+	let secs_of_day = secs % (60 * 60 * 24);
+	html! {
+		time datetime=(format_rfc3339(secs)) {
+			(format!(
+				"{:04}-{:02}-{:02} {:02}:{:02} UTC",
+				y,
+				m,
+				d,
+				secs_of_day / 3600,
+				secs_of_day % 3600 / 60
+			))
+		}
+	}
+}
+
+/// Format a Unix timestamp as an RFC 3339 UTC date and time
+pub fn format_rfc3339(secs: u32) -> String {
+	let (y, m, d) = civil_from_days(secs as i64 / (60 * 60 * 24));
+	let secs_of_day = secs % (60 * 60 * 24);
+	format!(
+		"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+		y,
+		m,
+		d,
+		secs_of_day / 3600,
+		secs_of_day % 3600 / 60,
+		secs_of_day % 60
+	)
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) proleptic
+/// Gregorian calendar date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+	// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+	let z = days + 719468;
+	let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+	let doe = z - era * 146097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+	let m = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+	(yoe + era * 400 + (m <= 2) as i64, m, d)
+}
+
+fn render_figcaption(img: &Image) -> Markup {
+	let mut file_info = Vec::<String>::new();
+	if img.audio {
+		file_info.push("♫".into());
+	}
+	if img.duration != 0 {
+		file_info.push(format_duration(img.duration));
+	}
+	file_info.push({
+		let s = img.size;
+		if s < 1 << 10 {
+			format!("{} B", s)
+		} else if s < 1 << 20 {
+			format!("{} KB", s / (1 << 10))
+		} else {
+			format!("{:.1} MB", s as f32 / (1 << 20) as f32)
+		}
+	});
+	if img.width != 0 || img.height != 0 {
+		file_info.push(format!("{}x{}", img.width, img.height));
+	}
+	if let Some(a) = &img.artist {
+		file_info.push(a.clone());
+		if img.title.is_some() {
+			file_info.push(" - ".into());
+		}
+	}
+	if let Some(t) = &img.title {
+		file_info.push(t.clone());
+	}
+
+	let name = format!("{}.{}", img.name, img.file_type.extension());
+
+	html! {
+		figcaption.spaced {
+			span.file-info {
+				@for s in &file_info {
+					span { (s) }
+				}
+			}
+			a href=(source_path(img)) download=(name) { (name) }
+		}
+	}
+}
+
+fn render_figure(img: &Image) -> Markup {
+	use FileType::*;
+
+	let (w, h, url) = if img.thumb_type == NoFile {
+		// No thumbnail exists
+		(
+			150,
+			150,
+			crate::assets::url(match img.file_type {
+				WEBM | MP4 | MP3 | OGG | FLAC => "audio.png",
+				_ => "file.png",
+			}),
+		)
+	} else if img.spoilered {
+		(150, 150, crate::assets::url("spoil/default.jpg"))
+	} else {
+		(img.thumb_width, img.thumb_height, thumb_path(img))
+	};
+
+	html! {
+		figure {
+			a href=(source_path(img)) target="_blank" {
+				img src=(url) width=(w) height=(h);
+			}
+		}
+	}
+}
+
+/// Format a duration in seconds as [[HH:]MM:]SS
+fn format_duration(secs: u32) -> String {
+	let mut s = String::new();
+	if secs >= 60 * 60 {
+		s += &format!("{:0>2}:", secs / (60 * 60));
+	}
+	if secs >= 60 {
+		s += &format!("{:0>2}:", secs / 60 % 60);
+	}
+	s += &format!("{:0>2}", secs % 60);
+	s
+}
+
+/// Get the thumbnail path of an upload
+pub fn thumb_path(img: &Image) -> String {
+	format!(
+		"/assets/images/thumb/{}.{}",
+		hex::encode(&img.sha1),
+		img.thumb_type.extension()
+	)
+}
+
+/// Get the source file path of an upload
+fn source_path(img: &Image) -> String {
+	format!(
+		"/assets/images/src/{}.{}",
+		hex::encode(&img.sha1),
+		img.file_type.extension()
+	)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn civil_dates() {
+		assert_eq!(civil_from_days(0), (1970, 1, 1));
+		assert_eq!(civil_from_days(11016), (2000, 2, 29));
+		assert_eq!(civil_from_days(19000), (2022, 1, 8));
+	}
+
+	#[test]
+	fn time() {
+		assert_eq!(
+			render_time(951827696).into_string(),
+			"<time datetime=\"2000-02-29T12:34:56Z\">\
+			2000-02-29 12:34 UTC</time>"
+		);
+	}
+
+	#[test]
+	fn durations() {
+		assert_eq!(format_duration(5), "05");
+		assert_eq!(format_duration(65), "01:05");
+		assert_eq!(format_duration(3725), "01:02:05");
+	}
+}
@@ -1,34 +1,34 @@
+mod antispam;
+mod api;
+mod assets;
 mod body;
+mod captcha;
+mod cli;
 mod client;
 mod config;
 mod db;
 mod feeds;
+mod html;
 mod message;
+mod metrics;
 mod mt_context;
 mod registry;
+mod search;
+mod shutdown;
+mod upkeep;
 mod util;
 
 use actix::prelude::*;
 use actix_web::{get, web, App, Error, HttpRequest, HttpResponse, HttpServer};
 use actix_web_actors::ws;
-use askama::Template;
 use cfg_if::cfg_if;
 use dotenv;
 use feeds::IndexFeed;
 use mt_context::MTAddr;
 use registry::Registry;
-use std::sync::Arc;
+use std::time::Duration;
 
 // TODO: asset routes
-// TODO: /robots.txt
-// TODO: /health-check
-
-// TODO: ETag support via hashing the source HTML and configuration
-#[derive(Template)]
-#[template(path = "index.html")]
-struct Index {
-	config: Arc<common::config::Public>,
-}
 
 #[get("/api/socket")]
 async fn connect(
@@ -37,30 +37,65 @@ async fn connect(
 	registry: web::Data<Addr<Registry>>,
 	index_feed: web::Data<MTAddr<IndexFeed>>,
 ) -> Result<HttpResponse, Error> {
-	let ci = req.connection_info();
-	let ip = if config::SERVER.reverse_proxied {
-		ci.realip_remote_addr()
-	} else {
-		ci.peer_addr()
-	}
-	.map(|s| s.parse::<std::net::SocketAddr>().ok())
-	.flatten()
-	.map(|a| a.ip())
-	.ok_or(actix_web::error::ErrorBadRequest(
-		"could not read client IP",
-	))?;
+	let ip = util::client_ip(&req)?;
+
+	// DB connections are bound to the global Tokio runtime
+	let ban = mt_context::TOKIO_RUNTIME
+		.spawn(db::get_ban(None, ip))
+		.await
+		.map_err(actix_web::error::ErrorInternalServerError)?
+		.map_err(actix_web::error::ErrorInternalServerError)?;
 
 	ws::start(
 		client::Client::new(
 			ip,
 			registry.get_ref().clone(),
 			index_feed.get_ref().clone(),
+			ban,
 		),
 		&req,
 		stream,
 	)
 }
 
+/// Liveness check. Responds as long as the process is running.
+#[get("/health")]
+async fn health() -> &'static str {
+	"ok"
+}
+
+/// Readiness check. Responds with 503, if the database is not reachable or
+/// the registry is not processing messages.
+#[get("/ready")]
+async fn ready(registry: web::Data<Addr<Registry>>) -> HttpResponse {
+	let res = tokio::time::timeout(Duration::from_secs(5), async {
+		// DB connections are bound to the global Tokio runtime
+		mt_context::TOKIO_RUNTIME.spawn(db::ping()).await??;
+		registry.send(registry::Ping).await?;
+		Ok::<(), util::Err>(())
+	})
+	.await;
+	match res {
+		Ok(Ok(())) => HttpResponse::Ok().body("ok"),
+		Ok(Err(e)) => HttpResponse::ServiceUnavailable().body(e.to_string()),
+		Err(_) => HttpResponse::ServiceUnavailable().body("timed out"),
+	}
+}
+
+/// Metrics in the Prometheus text exposition format
+#[get("/metrics")]
+async fn get_metrics(
+	registry: web::Data<Addr<Registry>>,
+) -> Result<HttpResponse, Error> {
+	Ok(HttpResponse::Ok()
+		.content_type("text/plain; version=0.0.4")
+		.body(
+			metrics::collect(&registry)
+				.await
+				.map_err(actix_web::error::ErrorInternalServerError)?,
+		))
+}
+
 #[actix_web::main]
 async fn main() -> Result<(), std::io::Error> {
 	async {
@@ -85,98 +120,250 @@ async fn main() -> Result<(), std::io::Error> {
 			})
 			.init()?;
 
-		// Set's a more descriptive process title - only port number because of
-		// 12 char limit.
-		// Also prevents exposing DB connection string in args, if any.
-		proctitle::set_title(format!(
-			"shamichan @ :{}",
-			config::SERVER
-				.address
-				.parse::<std::net::SocketAddr>()?
-				.port(),
-		));
-
-		// TODO: remove this and revert tokio runtime to private, when we switch
-		// to actix_web, askama and actix_web_actors to 4.0.
-		let threads = mt_context::TOKIO_RUNTIME.block_on(async {
-			db::open().await?;
-
-			db::get_all_threads_short().await
-		})?;
-
-		// Might as well register them to remove the need to fetch them later
-		body::cache_locations(
-			threads.iter().map(|t| t.posts.values()).flatten(),
-		);
-
-		// Spawn registry on it's own thread to reduce contention
-		let registry =
-			Registry::start_in_arbiter(&Arbiter::new().handle(), move |ctx| {
-				Registry::new(ctx, threads)
-			});
-		let index_feed = registry.send(registry::GetIndexFeed).await?;
-
-		let s = HttpServer::new(move || {
-			use actix_files::Files;
-			use actix_web::middleware::{
-				Compress, Logger, NormalizePath, TrailingSlash,
-			};
-
-			cfg_if! {
-				if #[cfg(debug_assertions)] {
-					let app = App::new().wrap_fn(|req, srv| {
-						use actix_service::Service;
-						use actix_web::http::{
-							header::{CACHE_CONTROL, HeaderValue},
-						};
-
-						let fut = srv.call(req);
-						async {
-							let mut res = fut.await?;
-							res.headers_mut().insert(
-								CACHE_CONTROL,
-								HeaderValue::from_static("no-store"),
-							);
-							Ok(res)
-						}
+		match &config::SERVER.command {
+			None | Some(config::Command::Serve) => serve().await,
+			Some(cmd) => mt_context::TOKIO_RUNTIME.block_on(async {
+				db::open().await?;
+				cli::run(cmd).await
+			}),
+		}
+	}
+	.await
+	.map_err(|err| {
+		std::io::Error::new(std::io::ErrorKind::Other, err.to_string())
+	})
+}
+
+/// Start the server and run it until stopped
+async fn serve() -> util::DynResult {
+	// Set's a more descriptive process title - only port number because of
+	// 12 char limit.
+	// Also prevents exposing DB connection string in args, if any.
+	proctitle::set_title(format!(
+		"shamichan @ :{}",
+		config::SERVER
+			.address
+			.parse::<std::net::SocketAddr>()?
+			.port(),
+	));
+
+	// TODO: remove this and revert tokio runtime to private, when we switch
+	// to actix_web, askama and actix_web_actors to 4.0.
+	let threads = mt_context::TOKIO_RUNTIME.block_on(async {
+		db::open().await?;
+
+		// Fall back to the defaults, if no configs are stored yet
+		if let Some(conf) = db::get_config().await? {
+			config::set(conf);
+		}
+
+		db::get_all_threads_short().await
+	})?;
+
+	// Might as well register them to remove the need to fetch them later
+	body::cache_locations(threads.iter().map(|t| t.posts.values()).flatten());
+
+	// Spawn registry on it's own thread to reduce contention
+	let registry =
+		Registry::start_in_arbiter(&Arbiter::new().handle(), move |ctx| {
+			Registry::new(ctx, threads)
+		});
+	let index_feed = registry.send(registry::GetIndexFeed).await?;
+
+	// Reload configs and propagate them to clients on each update
+	{
+		let registry = registry.clone();
+		mt_context::TOKIO_RUNTIME.spawn(async move {
+			if let Err(e) = db::listen_for_config_updates(|conf| {
+				config::set(conf);
+				registry.do_send(registry::BroadcastConfigs);
+			})
+			.await
+			{
+				log::error!("stopped listening for config updates: {}", e);
+			}
+		});
+	}
+
+	// Propagate thread deletions, including those performed by
+	// administrative commands from other processes
+	{
+		let registry = registry.clone();
+		mt_context::TOKIO_RUNTIME.spawn(async move {
+			if let Err(e) = db::listen_for_thread_deletions(|id| {
+				registry.do_send(registry::RemoveThreads(vec![id]));
+			})
+			.await
+			{
+				log::error!("stopped listening for thread deletions: {}", e);
+			}
+		});
+	}
+
+	// Propagate thread moderation performed by administrative commands
+	{
+		let registry = registry.clone();
+		mt_context::TOKIO_RUNTIME.spawn(async move {
+			if let Err(e) = db::listen_for_thread_moderation(|entry| {
+				registry.do_send(registry::ModerateThread(entry));
+			})
+			.await
+			{
+				log::error!("stopped listening for thread moderation: {}", e);
+			}
+		});
+	}
+
+	// Propagate staff account changes made by administrative commands
+	{
+		let registry = registry.clone();
+		mt_context::TOKIO_RUNTIME.spawn(async move {
+			if let Err(e) = db::listen_for_staff_updates(|s| {
+				registry.do_send(registry::SetStaff {
+					pub_key: s.public_key,
+					staff: s.staff,
+				});
+			})
+			.await
+			{
+				log::error!("stopped listening for staff updates: {}", e);
+			}
+		});
+	}
+
+	// Disconnect banned clients. Bans are propagated through the DB to all
+	// servers, regardless of where they were issued.
+	{
+		let registry = registry.clone();
+		mt_context::TOKIO_RUNTIME.spawn(async move {
+			if let Err(e) = db::listen_for_bans(|b| {
+				if let Some(pub_key) = b.public_key {
+					registry.do_send(registry::BanPubKey {
+						pub_key,
+						ban: b.notice.clone(),
 					});
-				} else {
-					let app = App::new();
 				}
-			};
-			let mut app = app
-				.wrap(Logger::default())
-				.wrap(NormalizePath::new(TrailingSlash::Trim))
-				.wrap(Compress::default())
-				.app_data(registry.clone())
-				.app_data(index_feed.clone())
-				.service(connect)
-				.service(Files::new("/assets", "./www"));
-
-			for p in &["/", "/catalog", "/threads/{thread:\\d+}/{page:\\d+}"] {
-				app = app.service(web::resource(*p).to(|| async {
-					let i = Index {
-						config: config::get().public.clone(),
-					};
-					match i.render() {
-						Ok(s) => s,
-						Err(e) => e.to_string(),
+				if let Some(ip) = b.ip {
+					match ip.parse() {
+						Ok(range) => registry.do_send(registry::BanIP {
+							range,
+							ban: b.notice,
+						}),
+						Err(e) => log::error!("invalid banned IP range: {}", e),
 					}
-				}));
+				}
+			})
+			.await
+			{
+				log::error!("stopped listening for bans: {}", e);
 			}
+		});
+	}
+
+	let shutdown_registry = registry.clone();
+	let s = HttpServer::new(move || {
+		use actix_files::Files;
+		use actix_web::middleware::{
+			Compress, Logger, NormalizePath, TrailingSlash,
+		};
 
-			app
-		})
-		.bind(&config::SERVER.address)?;
+		cfg_if! {
+			if #[cfg(debug_assertions)] {
+				let app = App::new().wrap_fn(|req, srv| {
+					use actix_service::Service;
+					use actix_web::http::{
+						header::{CACHE_CONTROL, HeaderValue},
+					};
 
-		log::info!("server started on http://{}", config::SERVER.address);
+					let fut = srv.call(req);
+					async {
+						let mut res = fut.await?;
+						res.headers_mut().insert(
+							CACHE_CONTROL,
+							HeaderValue::from_static("no-store"),
+						);
+						Ok(res)
+					}
+				});
+				let images = web::scope("/assets/images");
+				let assets = web::scope("/assets");
+			} else {
+				use actix_web::{
+					http::header::CACHE_CONTROL, middleware::DefaultHeaders,
+				};
 
-		s.run().await?;
+				let app = App::new();
 
-		Ok::<(), util::Err>(())
-	}
-	.await
-	.map_err(|err| {
-		std::io::Error::new(std::io::ErrorKind::Other, err.to_string())
+				// Uploaded images are addressed by their hashes and never
+				// change
+				let images = web::scope("/assets/images").wrap(
+					DefaultHeaders::new().add((
+						CACHE_CONTROL,
+						"public, max-age=31536000, immutable",
+					)),
+				);
+
+				// Other assets change with deployments. Requests using the
+				// current fingerprinted URL of an asset can be cached
+				// immutably. Always revalidate any other requests, such as
+				// those made by the client application, using the ETag and
+				// Last-Modified headers.
+				let assets = web::scope("/assets").wrap_fn(|req, srv| {
+					use actix_service::Service;
+					use actix_web::http::header::HeaderValue;
+
+					let immutable = crate::assets::is_fingerprinted(
+						req.path(),
+						req.query_string(),
+					);
+					let fut = srv.call(req);
+					async move {
+						let mut res = fut.await?;
+						res.headers_mut().insert(
+							CACHE_CONTROL,
+							HeaderValue::from_static(if immutable {
+								"public, max-age=31536000, immutable"
+							} else {
+								"no-cache"
+							}),
+						);
+						Ok(res)
+					}
+				});
+			}
+		};
+		app.wrap(Logger::default())
+			.wrap(NormalizePath::new(TrailingSlash::Trim))
+			.wrap(Compress::default())
+			.app_data(web::Data::new(registry.clone()))
+			.app_data(web::Data::new(index_feed.clone()))
+			.service(connect)
+			.service(health)
+			.service(ready)
+			.service(get_metrics)
+			.service(api::scope())
+			.service(html::atom::scope())
+			.service(html::robots_txt)
+			// Must be registered before the enclosing /assets scope
+			.service(images.service(Files::new("", &config::SERVER.image_dir)))
+			.service(assets.service(Files::new("", crate::assets::DIR)))
+			.service(web::resource("/").to(html::index))
+			.service(web::resource("/catalog").to(html::catalog))
+			.service(
+				web::resource("/threads/{thread:\\d+}/{page:\\d+}")
+					.to(html::thread),
+			)
 	})
+	// Signals are handled by shutdown::on_signal to flush state before
+	// stopping
+	.disable_signals()
+	.bind(&config::SERVER.address)?
+	.run();
+	actix_web::rt::spawn(shutdown::on_signal(s.handle(), shutdown_registry));
+
+	log::info!("server started on http://{}", config::SERVER.address);
+
+	s.await?;
+
+	Ok(())
 }
@@ -0,0 +1,275 @@
+use crate::{
+	mt_context::{AsyncHandler, MTAddr},
+	registry::{self, Registry},
+	util::DynResult,
+};
+use actix::prelude::*;
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+use tokio::sync::oneshot;
+
+lazy_static::lazy_static! {
+	/// Durations of DB queries by query name
+	static ref DB_QUERIES: Mutex<BTreeMap<&'static str, Summary>> =
+		Default::default();
+}
+
+/// Running count and sum of observed values. Exported as a Prometheus summary.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Summary {
+	pub count: u64,
+	pub sum: f64,
+}
+
+impl Summary {
+	/// Record an observed value
+	#[inline]
+	pub fn observe(&mut self, val: f64) {
+		self.count += 1;
+		self.sum += val;
+	}
+
+	/// Record an observed duration in seconds
+	#[inline]
+	pub fn observe_duration(&mut self, d: Duration) {
+		self.observe(d.as_secs_f64());
+	}
+
+	/// Add the observations of another summary to this one
+	#[inline]
+	pub fn merge(&mut self, other: &Self) {
+		self.count += other.count;
+		self.sum += other.sum;
+	}
+}
+
+/// Record the duration of a DB query
+pub fn observe_db_query(name: &'static str, d: Duration) {
+	DB_QUERIES
+		.lock()
+		.unwrap()
+		.entry(name)
+		.or_default()
+		.observe_duration(d);
+}
+
+/// Request for an MTContext actor to send its metrics over the channel
+pub struct GetMetrics<T>(pub oneshot::Sender<T>);
+
+impl<T> GetMetrics<T> {
+	/// Send the metrics to the requester, ignoring a dropped receiver
+	#[inline]
+	pub fn reply(self, metrics: T) {
+		std::mem::drop(self.0.send(metrics));
+	}
+}
+
+/// Metrics of a ThreadFeed or the IndexFeed
+#[derive(Debug, Default, Clone)]
+pub struct FeedMetrics {
+	/// Pages loaded from the DB, that can still change
+	pub mutable_pages: usize,
+
+	/// Pages loaded from the DB, that can no longer change
+	pub immutable_pages: usize,
+
+	/// Time spent processing buffered changes on each pulse
+	pub pulse_duration: Summary,
+}
+
+/// Metrics of the BodyFlusher
+#[derive(Debug, Default, Clone)]
+pub struct BodyFlusherMetrics {
+	/// Number of open post bodies written to the DB per flush
+	pub batch_size: Summary,
+}
+
+/// Metrics of the Registry and the addresses of the actors it manages
+#[derive(MessageResponse)]
+pub struct RegistryMetrics {
+	/// Number of connected clients
+	pub clients: usize,
+
+	/// Number of clients synchronized to each feed with any clients
+	pub feed_clients: Vec<(u64, usize)>,
+
+	pub actors: registry::ManagedActors,
+}
+
+/// Request to send a message to an MTContext actor and await its metrics
+async fn request<A, T>(addr: &MTAddr<A>) -> DynResult<T>
+where
+	A: Actor<Context = crate::mt_context::MTContext<A>>
+		+ AsyncHandler<GetMetrics<T>>
+		+ Send,
+	T: Send + 'static,
+{
+	let (send, receive) = oneshot::channel();
+	addr.do_send(GetMetrics(send));
+	Ok(receive.await?)
+}
+
+/// Collect metrics from all actors and encode them in the Prometheus text
+/// exposition format
+pub async fn collect(registry: &Addr<Registry>) -> DynResult<String> {
+	let reg = registry.send(registry::GetMetrics).await?;
+	let actors = &reg.actors;
+
+	let mut thread_feeds = FeedMetrics::default();
+	for m in futures::future::join_all(
+		actors.thread_feeds.iter().map(request::<_, FeedMetrics>),
+	)
+	.await
+	{
+		// Feeds stopped since the registry responded have no metrics to
+		// contribute
+		if let Ok(m) = m {
+			thread_feeds.mutable_pages += m.mutable_pages;
+			thread_feeds.immutable_pages += m.immutable_pages;
+			thread_feeds.pulse_duration.merge(&m.pulse_duration);
+		}
+	}
+	let index_feed: FeedMetrics = request(&actors.index_feed).await?;
+	let body_flusher: BodyFlusherMetrics =
+		request(&actors.body_flusher).await?;
+
+	let mut w = String::new();
+
+	header(&mut w, "clients", "gauge", "Connected clients")?;
+	writeln!(w, "shamichan_clients {}", reg.clients)?;
+
+	header(
+		&mut w,
+		"feed_clients",
+		"gauge",
+		"Clients synchronized to a feed. Feed 0 is the thread index.",
+	)?;
+	for (feed, n) in &reg.feed_clients {
+		writeln!(w, "shamichan_feed_clients{{feed=\"{}\"}} {}", feed, n)?;
+	}
+
+	header(&mut w, "thread_feeds", "gauge", "Live thread feeds")?;
+	writeln!(w, "shamichan_thread_feeds {}", actors.thread_feeds.len())?;
+
+	header(&mut w, "pages", "gauge", "Thread pages loaded from the DB")?;
+	writeln!(
+		w,
+		"shamichan_pages{{kind=\"mutable\"}} {}",
+		thread_feeds.mutable_pages
+	)?;
+	writeln!(
+		w,
+		"shamichan_pages{{kind=\"immutable\"}} {}",
+		thread_feeds.immutable_pages
+	)?;
+
+	header(
+		&mut w,
+		"pulse_duration_seconds",
+		"summary",
+		"Time spent processing buffered feed changes",
+	)?;
+	summary(
+		&mut w,
+		"pulse_duration_seconds",
+		"feed=\"thread\"",
+		&thread_feeds.pulse_duration,
+	)?;
+	summary(
+		&mut w,
+		"pulse_duration_seconds",
+		"feed=\"index\"",
+		&index_feed.pulse_duration,
+	)?;
+
+	header(
+		&mut w,
+		"body_flush_batch_size",
+		"summary",
+		"Open post bodies written to the DB per flush",
+	)?;
+	summary(
+		&mut w,
+		"body_flush_batch_size",
+		"",
+		&body_flusher.batch_size,
+	)?;
+
+	header(
+		&mut w,
+		"db_query_duration_seconds",
+		"summary",
+		"DB query durations",
+	)?;
+	// Copy to not hold the lock while writing
+	let queries = DB_QUERIES.lock().unwrap().clone();
+	for (name, s) in &queries {
+		summary(
+			&mut w,
+			"db_query_duration_seconds",
+			&format!("query=\"{}\"", name),
+			s,
+		)?;
+	}
+
+	Ok(w)
+}
+
+/// Write the HELP and TYPE lines of a metric
+fn header(
+	w: &mut String,
+	name: &str,
+	typ: &str,
+	help: &str,
+) -> std::fmt::Result {
+	writeln!(w, "# HELP shamichan_{} {}", name, help)?;
+	writeln!(w, "# TYPE shamichan_{} {}", name, typ)
+}
+
+/// Write the sum and count samples of a summary with optional labels
+fn summary(
+	w: &mut String,
+	name: &str,
+	labels: &str,
+	s: &Summary,
+) -> std::fmt::Result {
+	let labels = if labels.is_empty() {
+		String::new()
+	} else {
+		format!("{{{}}}", labels)
+	};
+	writeln!(w, "shamichan_{}_sum{} {}", name, labels, s.sum)?;
+	writeln!(w, "shamichan_{}_count{} {}", name, labels, s.count)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn summary_merge() {
+		let mut a = Summary::default();
+		a.observe(1.5);
+		a.observe(2.0);
+		let mut b = Summary::default();
+		b.observe_duration(Duration::from_millis(500));
+		a.merge(&b);
+		assert_eq!(a.count, 3);
+		assert_eq!(a.sum, 4.0);
+	}
+
+	#[test]
+	fn summary_encoding() {
+		let mut s = Summary::default();
+		s.observe(2.0);
+		let mut w = String::new();
+		summary(&mut w, "test", "", &s).unwrap();
+		summary(&mut w, "test", "feed=\"index\"", &s).unwrap();
+		assert_eq!(
+			w,
+			"shamichan_test_sum 2\n\
+			shamichan_test_count 1\n\
+			shamichan_test_sum{feed=\"index\"} 2\n\
+			shamichan_test_count{feed=\"index\"} 1\n"
+		);
+	}
+}
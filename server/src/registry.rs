@@ -1,17 +1,28 @@
 use crate::{
 	body::persist_open::BodyFlusher,
-	client::Client,
+	client::{self, Client},
+	config,
 	feeds::{self, AnyFeed, IndexFeed, ThreadFeed},
+	message::Message,
 	mt_context::{run, MTAddr},
+	upkeep::Upkeep,
 	util::{self, SnapshotSource, WakeUp},
 };
 use actix::dev::MessageResponse;
 use actix::prelude::*;
 use common::{
-	payloads::{Thread, ThreadWithPosts},
+	payloads::{
+		moderation::{BanNotice, PostModerationEntry},
+		Thread, ThreadWithPosts,
+	},
 	util::SetMap,
+	Encoder, MessageType,
+};
+use std::{
+	collections::HashMap,
+	net::IpAddr,
+	time::{Duration, Instant},
 };
-use std::collections::HashMap;
 
 /// Stores client state and address
 #[derive(Debug)]
@@ -23,6 +34,15 @@ struct ClientDescriptor {
 	/// The internal public key ID the client is registered with
 	pub_key: Option<u64>,
 
+	/// The client's public key has a staff account
+	staff: bool,
+
+	/// ID of the post the client is currently editing
+	open_post: Option<u64>,
+
+	/// IP address of the client
+	ip: IpAddr,
+
 	/// Address for communication
 	addr: Addr<Client>,
 }
@@ -39,6 +59,17 @@ pub struct Registry {
 	/// Maps client public key ID to a set of clients using that ID
 	by_pub_key: SetMap<u64, u64>,
 
+	/// Maps open post IDs to the clients currently editing them
+	open_posts: HashMap<u64, u64>,
+
+	/// Time the last client using a public key ID disconnected, for public
+	/// keys with no connected clients
+	disconnected_pub_keys: HashMap<u64, Instant>,
+
+	/// Time the registry was started. Counts as the disconnection time of any
+	/// public key not connected since.
+	started_at: Instant,
+
 	/// Thread index feed
 	index_feed: MTAddr<IndexFeed>,
 
@@ -47,6 +78,9 @@ pub struct Registry {
 
 	/// All thread feeds in the system. One per existing thread.
 	feeds: HashMap<u64, MTAddr<ThreadFeed>>,
+
+	/// Periodic cleanup task runner
+	upkeep: MTAddr<Upkeep>,
 }
 
 impl Actor for Registry {
@@ -112,6 +146,10 @@ impl Registry {
 			clients: Default::default(),
 			feed_clients: Default::default(),
 			by_pub_key: Default::default(),
+			open_posts: Default::default(),
+			disconnected_pub_keys: Default::default(),
+			started_at: Instant::now(),
+			upkeep: run(Upkeep::new(ctx.address())),
 			index_feed: index_feed.clone(),
 			body_flusher: body_flusher.clone(),
 			feeds: feed_init_data
@@ -164,6 +202,7 @@ impl Registry {
 #[rtype(result = "()")]
 pub struct RegisterClient {
 	pub id: u64,
+	pub ip: IpAddr,
 	pub addr: Addr<Client>,
 }
 
@@ -180,6 +219,9 @@ impl Handler<RegisterClient> for Registry {
 			ClientDescriptor {
 				feed: None,
 				pub_key: None,
+				staff: false,
+				open_post: None,
+				ip: msg.ip,
 				addr: msg.addr,
 			},
 		);
@@ -207,8 +249,14 @@ impl Handler<UnregisterClient> for Registry {
 				}
 				self.wake_up_feed(feed);
 			}
+			if let Some(post) = desc.open_post {
+				self.open_posts.remove(&post);
+			}
 			if let Some(pub_key) = desc.pub_key {
 				self.by_pub_key.remove(&pub_key, &client);
+				if self.by_pub_key.get(&pub_key).is_none() {
+					self.disconnected_pub_keys.insert(pub_key, Instant::now());
+				}
 			}
 		}
 	}
@@ -274,6 +322,7 @@ impl Handler<SetFeed> for Registry {
 pub struct SetPublicKey {
 	pub client: u64,
 	pub pub_key: u64,
+	pub staff: bool,
 }
 
 impl Handler<SetPublicKey> for Registry {
@@ -281,16 +330,305 @@ impl Handler<SetPublicKey> for Registry {
 
 	fn handle(
 		&mut self,
-		SetPublicKey { client, pub_key }: SetPublicKey,
+		SetPublicKey {
+			client,
+			pub_key,
+			staff,
+		}: SetPublicKey,
 		_: &mut Self::Context,
 	) -> Self::Result {
 		let desc = self.get_client(&client)?;
 		desc.pub_key = Some(pub_key);
+		desc.staff = staff;
 		self.by_pub_key.insert(pub_key, client);
+		self.disconnected_pub_keys.remove(&pub_key);
 		Ok(())
 	}
 }
 
+/// Set or clear the post a client is currently editing.
+///
+/// Returns false and changes nothing, if the post is already being edited by
+/// another client.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct SetOpenPost {
+	pub client: u64,
+	pub post: Option<u64>,
+}
+
+impl Handler<SetOpenPost> for Registry {
+	type Result = bool;
+
+	fn handle(
+		&mut self,
+		SetOpenPost { client, post }: SetOpenPost,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		if let Some(post) = post {
+			match self.open_posts.get(&post) {
+				Some(owner) if owner != &client => return false,
+				_ => (),
+			}
+		}
+
+		let desc = match self.clients.get_mut(&client) {
+			Some(d) => d,
+			None => return false,
+		};
+		if let Some(old) = std::mem::replace(&mut desc.open_post, post) {
+			self.open_posts.remove(&old);
+		}
+		if let Some(post) = post {
+			self.open_posts.insert(post, client);
+		}
+		true
+	}
+}
+
+/// Notify the client editing an open post, if any, that the post was closed by
+/// the server
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct OpenPostClosed(pub client::OpenPostClosed);
+
+impl Handler<OpenPostClosed> for Registry {
+	type Result = ();
+
+	fn handle(
+		&mut self,
+		OpenPostClosed(msg): OpenPostClosed,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		if let Some(client) = self.open_posts.remove(&msg.id) {
+			if let Some(desc) = self.clients.get_mut(&client) {
+				desc.open_post = None;
+				desc.addr.do_send(msg);
+			}
+		}
+	}
+}
+
+/// Filter public key IDs to only those, that have had no connected clients for
+/// at least min_duration
+#[derive(Message)]
+#[rtype(result = "Vec<u64>")]
+pub struct OfflinePubKeys {
+	pub pub_keys: Vec<u64>,
+	pub min_duration: Duration,
+}
+
+impl Handler<OfflinePubKeys> for Registry {
+	type Result = Vec<u64>;
+
+	fn handle(
+		&mut self,
+		OfflinePubKeys {
+			pub_keys,
+			min_duration,
+		}: OfflinePubKeys,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		// Records older than min_duration carry no more information than the
+		// fallback to started_at
+		self.disconnected_pub_keys
+			.retain(|_, t| t.elapsed() < min_duration);
+
+		pub_keys
+			.into_iter()
+			.filter(|k| {
+				self.by_pub_key.get(k).is_none()
+					&& self
+						.disconnected_pub_keys
+						.get(k)
+						.unwrap_or(&self.started_at)
+						.elapsed() >= min_duration
+			})
+			.collect()
+	}
+}
+
+/// Disconnect all clients using a banned public key
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BanPubKey {
+	pub pub_key: u64,
+	pub ban: BanNotice,
+}
+
+impl Handler<BanPubKey> for Registry {
+	type Result = ();
+
+	fn handle(
+		&mut self,
+		BanPubKey { pub_key, ban }: BanPubKey,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		if let Some(clients) = self.by_pub_key.get(&pub_key) {
+			for id in clients {
+				if let Some(desc) = self.clients.get(id) {
+					desc.addr.do_send(client::Ban(ban.clone()));
+				}
+			}
+		}
+	}
+}
+
+/// Update the staff status of all clients using a public key
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetStaff {
+	pub pub_key: u64,
+	pub staff: bool,
+}
+
+impl Handler<SetStaff> for Registry {
+	type Result = ();
+
+	fn handle(
+		&mut self,
+		SetStaff { pub_key, staff }: SetStaff,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		if let Some(clients) = self.by_pub_key.get(&pub_key) {
+			for id in clients {
+				if let Some(desc) = self.clients.get_mut(id) {
+					desc.staff = staff;
+				}
+			}
+		}
+	}
+}
+
+/// Send a message to all staff clients synchronized to any of the feeds
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SendToStaff {
+	pub feeds: Vec<u64>,
+	pub message: Message,
+}
+
+impl Handler<SendToStaff> for Registry {
+	type Result = ();
+
+	fn handle(
+		&mut self,
+		SendToStaff { feeds, message }: SendToStaff,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		for desc in self.clients.values() {
+			if desc.staff && matches!(desc.feed, Some(f) if feeds.contains(&f))
+			{
+				desc.addr.do_send(client::SendMessage(message.clone()));
+			}
+		}
+	}
+}
+
+/// Disconnect all clients connected from a banned IP address or range
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BanIP {
+	pub range: util::IpRange,
+	pub ban: BanNotice,
+}
+
+impl Handler<BanIP> for Registry {
+	type Result = ();
+
+	fn handle(
+		&mut self,
+		BanIP { range, ban }: BanIP,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		for desc in self.clients.values() {
+			if range.contains(&desc.ip) {
+				desc.addr.do_send(client::Ban(ban.clone()));
+			}
+		}
+	}
+}
+
+/// Propagate a moderation action applied to a thread outside of the server
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ModerateThread(pub PostModerationEntry);
+
+impl Handler<ModerateThread> for Registry {
+	type Result = ();
+
+	fn handle(
+		&mut self,
+		ModerateThread(PostModerationEntry { id, entry }): ModerateThread,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		if let Some(f) = self.feeds.get(&id) {
+			f.do_send(feeds::ModeratePost {
+				loc: feeds::PostLocation { id, page: 0 },
+				entry,
+			});
+		}
+	}
+}
+
+/// Stop the feeds of deleted threads and remove them from the thread index
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RemoveThreads(pub Vec<u64>);
+
+impl Handler<RemoveThreads> for Registry {
+	type Result = ();
+
+	fn handle(
+		&mut self,
+		RemoveThreads(threads): RemoveThreads,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		crate::body::forget_thread_locations(&threads);
+		for id in threads {
+			if let Some(f) = self.feeds.remove(&id) {
+				f.do_send(feeds::StopFeed {
+					clients: self
+						.feed_clients
+						.remove(&id)
+						.map(|mut s| s.snapshot())
+						.unwrap_or_default(),
+				});
+			} else {
+				self.index_feed.do_send(feeds::RemoveThread(id));
+			}
+		}
+	}
+}
+
+/// Send the current public configurations to all connected clients
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BroadcastConfigs;
+
+impl Handler<BroadcastConfigs> for Registry {
+	type Result = ();
+
+	fn handle(
+		&mut self,
+		_: BroadcastConfigs,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		let conf = config::get().public.clone();
+		common::log_msg_out!(MessageType::Configs, &conf);
+		let msg = match Encoder::encode(MessageType::Configs, &conf) {
+			Ok(buf) => client::SendMessage(Message::new(buf)),
+			Err(e) => {
+				log::error!("failed to encode configs: {}", e);
+				return;
+			}
+		};
+		for desc in self.clients.values() {
+			desc.addr.do_send(msg.clone());
+		}
+	}
+}
+
 /// Retrieve a ThreadFeed address from the registry
 #[derive(Message)]
 #[rtype(result = "Result<MTAddr<ThreadFeed>, String>")]
@@ -428,3 +766,103 @@ impl Handler<GetIndexFeed> for Registry {
 		self.index_feed.clone()
 	}
 }
+
+/// Addresses of the MTContext actors managed by the Registry
+#[derive(MessageResponse)]
+pub struct ManagedActors {
+	pub thread_feeds: Vec<MTAddr<ThreadFeed>>,
+	pub index_feed: MTAddr<IndexFeed>,
+	pub body_flusher: MTAddr<BodyFlusher>,
+	pub upkeep: MTAddr<Upkeep>,
+}
+
+impl Registry {
+	/// Collect the addresses of all managed MTContext actors
+	fn managed_actors(&self) -> ManagedActors {
+		ManagedActors {
+			thread_feeds: self.feeds.values().cloned().collect(),
+			index_feed: self.index_feed.clone(),
+			body_flusher: self.body_flusher.clone(),
+			upkeep: self.upkeep.clone(),
+		}
+	}
+}
+
+/// Retrieve the addresses of all MTContext actors managed by the Registry
+#[derive(Message)]
+#[rtype(result = "ManagedActors")]
+pub struct GetManagedActors;
+
+impl Handler<GetManagedActors> for Registry {
+	type Result = ManagedActors;
+
+	fn handle(
+		&mut self,
+		_: GetManagedActors,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		self.managed_actors()
+	}
+}
+
+/// Collect the Registry's metrics and the addresses of the actors it manages
+#[derive(Message)]
+#[rtype(result = "crate::metrics::RegistryMetrics")]
+pub struct GetMetrics;
+
+impl Handler<GetMetrics> for Registry {
+	type Result = crate::metrics::RegistryMetrics;
+
+	fn handle(&mut self, _: GetMetrics, _: &mut Self::Context) -> Self::Result {
+		crate::metrics::RegistryMetrics {
+			clients: self.clients.len(),
+			feed_clients: self
+				.feed_clients
+				.iter()
+				.filter(|(_, c)| !c.is_empty())
+				.map(|(id, c)| (*id, c.len()))
+				.collect(),
+			actors: self.managed_actors(),
+		}
+	}
+}
+
+/// Notify all connected clients of a server shutdown and disconnect them.
+/// Contains the number of seconds clients should wait before reconnecting.
+/// Resolves, once the message handlers of all clients have processed their
+/// pending messages and stopped.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RestartClients(pub u32);
+
+impl Handler<RestartClients> for Registry {
+	type Result = ResponseFuture<()>;
+
+	fn handle(
+		&mut self,
+		RestartClients(delay): RestartClients,
+		_: &mut Self::Context,
+	) -> Self::Result {
+		let sent: Vec<_> = self
+			.clients
+			.values()
+			.map(|desc| desc.addr.send(client::Restart(delay)))
+			.collect();
+		Box::pin(async move {
+			// Clients that already stopped have nothing left to disconnect
+			futures::future::join_all(sent).await;
+		})
+	}
+}
+
+/// Check the Registry is processing messages
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Ping;
+
+impl Handler<Ping> for Registry {
+	type Result = ();
+
+	#[inline]
+	fn handle(&mut self, _: Ping, _: &mut Self::Context) -> Self::Result {}
+}
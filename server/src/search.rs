@@ -0,0 +1,144 @@
+use crate::{db, util::DynResult};
+use common::payloads::{post_body::Node, SearchReq, SearchRes};
+
+/// Number of posts per page of search results
+const PAGE_SIZE: u32 = 20;
+
+/// Render a post body node tree as plain text for full-text search indexing.
+/// Code highlighting markup is stripped, leaving only the code itself.
+pub fn index_text(n: &Node) -> String {
+	let mut w = String::new();
+	write_text(n, &mut w);
+	w
+}
+
+fn write_text(n: &Node, w: &mut String) {
+	use Node::*;
+
+	match n {
+		Empty => (),
+		Text(s) | URL(s) | Embed { url: s, .. } => w.push_str(s),
+		Newline => w.push('\n'),
+		Children(v) => {
+			for n in v {
+				write_text(n, w);
+			}
+		}
+		PostLink { id, .. } => *w += &format!(">>{}", id),
+		Command(comm) => *w += &comm.format().0,
+		Reference { label, .. } => *w += &format!(">>>/{}/", label),
+		Code(html) => write_code(html, w),
+		Spoiler(ch) | Quoted(ch) | Bold(ch) | Italic(ch) => write_text(ch, w),
+		Pending(n) => *w += &n.format(),
+	}
+	// Separate words of adjacent nodes
+	w.push(' ');
+}
+
+/// Strip tags from highlighted code HTML and decode its entities.
+/// Line breaks are converted to newlines.
+fn write_code(html: &str, w: &mut String) {
+	let mut text = String::with_capacity(html.len());
+	let mut tag: Option<String> = None;
+	for ch in html.chars() {
+		match (ch, &mut tag) {
+			('<', None) => tag = Some(String::new()),
+			('>', Some(t)) => {
+				if t.trim_end_matches('/').trim().eq_ignore_ascii_case("br") {
+					text.push('\n');
+				}
+				tag = None;
+			}
+			(_, Some(t)) => t.push(ch),
+			(_, None) => text.push(ch),
+		}
+	}
+	match htmlescape::decode_html(&text) {
+		Ok(s) => w.push_str(&s),
+		Err(_) => w.push_str(&text),
+	}
+}
+
+/// Validate a search request
+pub fn validate(req: &SearchReq) -> Result<(), String> {
+	let l = req.query.chars().count();
+	if l == 0 || l > 100 {
+		return Err(format!("invalid query length: {}", l));
+	}
+	if let Some(tag) = &req.tag {
+		let l = tag.chars().count();
+		if l == 0 || l > 20 {
+			return Err(format!("invalid tag length: {}", l));
+		}
+	}
+	if req.page > 50 {
+		return Err(format!("search page out of range: {}", req.page));
+	}
+	Ok(())
+}
+
+/// Perform a validated search request
+pub async fn search(req: SearchReq) -> DynResult<SearchRes> {
+	// Fetch one extra post to detect further pages
+	let mut posts = db::search_posts(
+		&req.query,
+		req.tag.as_deref(),
+		req.thread,
+		PAGE_SIZE + 1,
+		req.page * PAGE_SIZE,
+	)
+	.await?;
+	let has_more = posts.len() > PAGE_SIZE as usize;
+	posts.truncate(PAGE_SIZE as usize);
+
+	Ok(SearchRes {
+		query: req.query,
+		page: req.page,
+		posts,
+		has_more,
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use common::payloads::post_body::Command;
+
+	#[test]
+	fn plain_text() {
+		assert_eq!(
+			index_text(&Node::Children(vec![
+				Node::Text("a".into()),
+				Node::Newline,
+				Node::Spoiler(Box::new(Node::Text("b".into()))),
+				Node::Code(
+					"<span class=\"syntex-source\">c &amp;&amp; d</span>"
+						.into()
+				),
+				Node::Code("e<br>f<br/>g".into()),
+				Node::Command(Command::Flip(false)),
+			]))
+			.split_whitespace()
+			.collect::<Vec<_>>(),
+			["a", "b", "c", "&&", "d", "e", "f", "g", "#flip", "(flop)"],
+		);
+	}
+
+	#[test]
+	fn validation() {
+		let mut req = SearchReq {
+			query: "a".into(),
+			tag: None,
+			thread: None,
+			page: 0,
+		};
+		assert!(validate(&req).is_ok());
+
+		req.query = String::new();
+		assert!(validate(&req).is_err());
+
+		req.query = "a".into();
+		req.tag = Some("a".repeat(21));
+		assert!(validate(&req).is_err());
+	}
+}
@@ -0,0 +1,93 @@
+use crate::{
+	mt_context::{AsyncHandler, MTAddr, MTContext},
+	registry::{self, Registry},
+	util::{DynResult, Flush, Stop},
+};
+use actix::prelude::*;
+use actix_web::dev::ServerHandle;
+
+/// Wait for SIGINT or SIGTERM and gracefully shut down the server
+pub async fn on_signal(server: ServerHandle, registry: Addr<Registry>) {
+	if let Err(e) = wait_for_signal().await {
+		log::error!("failed to listen for signals: {}", e);
+		return;
+	}
+
+	log::info!("shutting down");
+	if let Err(e) = shutdown(&server, &registry).await {
+		log::error!("failed to flush state on shutdown: {}", e);
+	}
+	server.stop(true).await;
+}
+
+/// Resolves on receiving either SIGINT or SIGTERM
+async fn wait_for_signal() -> std::io::Result<()> {
+	use actix_rt::signal::{
+		ctrl_c,
+		unix::{signal, SignalKind},
+	};
+
+	let mut term = signal(SignalKind::terminate())?;
+	futures::future::select(Box::pin(ctrl_c()), Box::pin(term.recv())).await;
+	Ok(())
+}
+
+/// Stop accepting connections, disconnect all clients and persist all
+/// buffered state
+async fn shutdown(
+	server: &ServerHandle,
+	registry: &Addr<Registry>,
+) -> DynResult {
+	server.pause().await;
+
+	// Stop periodic cleanup and wait for any running task to complete, so it
+	// makes no more changes concurrently with or after the flush
+	stop(&registry.send(registry::GetManagedActors).await?.upkeep).await;
+
+	// Disconnect clients and wait for their message handlers to stop, so no
+	// more changes are made after the flush
+	registry
+		.send(registry::RestartClients(
+			crate::config::SERVER.reconnect_delay,
+		))
+		.await?;
+
+	// Feeds pass any pending open post bodies to the BodyFlusher on flush, so
+	// it must be flushed only after them
+	let actors = registry.send(registry::GetManagedActors).await?;
+	futures::future::join_all(actors.thread_feeds.iter().map(flush)).await;
+	flush(&actors.index_feed).await;
+	flush(&actors.body_flusher).await;
+
+	// DB connections are bound to the global Tokio runtime
+	crate::mt_context::TOKIO_RUNTIME
+		.spawn(crate::antispam::flush_spam_scores())
+		.await??;
+
+	Ok(())
+}
+
+/// Flush the buffered state of an MTContext actor and wait for completion
+async fn flush<A>(addr: &MTAddr<A>)
+where
+	A: Actor<Context = MTContext<A>> + AsyncHandler<Flush> + Send,
+{
+	let (send, receive) = tokio::sync::oneshot::channel();
+	addr.do_send(Flush(send));
+
+	// Stopped actors drop the sender and have nothing to flush
+	std::mem::drop(receive.await);
+}
+
+/// Stop an MTContext actor and wait for it to process all previously received
+/// messages
+async fn stop<A>(addr: &MTAddr<A>)
+where
+	A: Actor<Context = MTContext<A>> + AsyncHandler<Stop> + Send,
+{
+	let (send, receive) = tokio::sync::oneshot::channel();
+	addr.do_send(Stop(send));
+
+	// Already stopped actors drop the sender
+	std::mem::drop(receive.await);
+}
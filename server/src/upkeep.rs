@@ -0,0 +1,249 @@
+use crate::{
+	client, config, db,
+	feeds::{PostLocation, ReadOpenBody},
+	mt_context::{AsyncHandler, MTContext},
+	registry::{self, Registry},
+	util::{self, DynResult},
+};
+use actix::prelude::*;
+use async_trait::async_trait;
+use common::payloads::FileType;
+use std::{collections::HashSet, time::Duration};
+use tokio::sync::oneshot;
+
+/// Periodically runs cleanup tasks on the database and feeds
+#[derive(Debug)]
+pub struct Upkeep {
+	/// Link to the global registry
+	registry: Addr<Registry>,
+}
+
+impl Actor for Upkeep {
+	type Context = MTContext<Self>;
+
+	fn started(&mut self, ctx: &mut Self::Context) {
+		ctx.notify_interval(FlushSpamScores, Duration::from_secs(1));
+		ctx.notify_interval(CloseAbandonedPosts, Duration::from_secs(60));
+		ctx.notify_interval(CollectGarbage, Duration::from_secs(60));
+		ctx.notify_interval(DeleteExpiredThreads, Duration::from_secs(60 * 10));
+	}
+}
+
+impl Upkeep {
+	pub fn new(registry: Addr<Registry>) -> Self {
+		Self { registry }
+	}
+
+	/// Close an abandoned open post the same way as if the client closed it
+	/// and notify the client editing it, if still connected.
+	///
+	/// The feed's copy of the body is used, as it can be newer than the last
+	/// one persisted to the DB.
+	async fn close_post(&self, p: db::OpenPostDesc) -> DynResult {
+		let feed = self.registry.send(registry::GetFeed(p.thread)).await??;
+		let loc = PostLocation {
+			page: p.page,
+			id: p.id,
+		};
+
+		let (send, receive) = oneshot::channel();
+		feed.do_send(ReadOpenBody {
+			loc: loc.clone(),
+			reply: send,
+		});
+		let body = match receive.await? {
+			Some(b) => b,
+			// Closed in the meantime
+			None => return Ok(()),
+		};
+
+		if let Some(body) = crate::body::close_post(&feed, loc, body).await? {
+			self.registry.do_send(registry::OpenPostClosed(
+				client::OpenPostClosed { id: p.id, body },
+			));
+		}
+		Ok(())
+	}
+}
+
+/// Flush buffered spam score increments to the DB
+#[derive(Clone)]
+struct FlushSpamScores;
+
+#[async_trait]
+impl AsyncHandler<FlushSpamScores> for Upkeep {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		_: FlushSpamScores,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		crate::antispam::flush_spam_scores().await
+	}
+}
+
+/// Delete expired rows from all tables inheriting from expiries, like pending
+/// images, bans and spam scores, expired in-memory spam scores of IP addresses
+/// and any images no longer used by posts together with their files
+#[derive(Clone)]
+struct CollectGarbage;
+
+#[async_trait]
+impl AsyncHandler<CollectGarbage> for Upkeep {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		_: CollectGarbage,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		crate::antispam::expire_in_memory_scores();
+
+		// Expired pending images can hold the last reference to an image, so
+		// delete them first
+		db::delete_expired().await?;
+
+		for img in db::delete_unused_images().await? {
+			let sha1 = img.sha1.clone();
+			if let Err(e) = delete_image_files(img).await {
+				log::error!("failed to delete files of image {}: {}", sha1, e);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Remove the source file and thumbnail of a deleted image. Already missing
+/// files are ignored.
+async fn delete_image_files(img: db::DeletedImage) -> DynResult {
+	let dir = &config::SERVER.image_dir;
+	let mut paths = vec![dir.join("src").join(format!(
+		"{}.{}",
+		img.sha1,
+		img.file_type.extension()
+	))];
+	if img.thumb_type != FileType::NoFile {
+		paths.push(dir.join("thumb").join(format!(
+			"{}.{}",
+			img.sha1,
+			img.thumb_type.extension()
+		)));
+	}
+
+	actix_web::web::block(move || -> std::io::Result<()> {
+		for p in paths {
+			match std::fs::remove_file(&p) {
+				Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+					return Err(e)
+				}
+				_ => (),
+			}
+		}
+		Ok(())
+	})
+	.await??;
+	Ok(())
+}
+
+/// Delete threads not bumped within the configured thread expiry and remove
+/// them from the feeds
+#[derive(Clone)]
+struct DeleteExpiredThreads;
+
+#[async_trait]
+impl AsyncHandler<DeleteExpiredThreads> for Upkeep {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		_: DeleteExpiredThreads,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		let conf = config::get();
+		if !conf.public.prune_threads || conf.public.thread_expiry == 0 {
+			return Ok(());
+		}
+
+		let threads =
+			db::delete_expired_threads(conf.public.thread_expiry).await?;
+		if !threads.is_empty() {
+			self.registry.do_send(registry::RemoveThreads(threads));
+		}
+		Ok(())
+	}
+}
+
+/// Close any open posts, that have been open for too long or whose author has
+/// not been connected for a while
+#[derive(Clone)]
+struct CloseAbandonedPosts;
+
+#[async_trait]
+impl AsyncHandler<CloseAbandonedPosts> for Upkeep {
+	type Error = util::Err;
+
+	async fn handle(
+		&mut self,
+		_: CloseAbandonedPosts,
+		_: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		let limits = config::get().open_post_limits.clone();
+		let posts = db::get_open_posts().await?;
+		if posts.is_empty() {
+			return Ok(());
+		}
+
+		let offline: HashSet<u64> = self
+			.registry
+			.send(registry::OfflinePubKeys {
+				pub_keys: posts
+					.iter()
+					.filter_map(|p| p.public_key)
+					.collect::<HashSet<_>>()
+					.into_iter()
+					.collect(),
+				min_duration: Duration::from_secs(
+					limits.max_owner_absence as u64,
+				),
+			})
+			.await?
+			.into_iter()
+			.collect();
+
+		let now = util::now();
+		for p in posts {
+			// Posts without a public key can never be reclaimed
+			let owner_offline =
+				p.public_key.map(|k| offline.contains(&k)).unwrap_or(true);
+			if !owner_offline
+				&& now.saturating_sub(p.created_on) < limits.max_age
+			{
+				continue;
+			}
+
+			let id = p.id;
+			if let Err(e) = self.close_post(p).await {
+				log::error!("failed to close abandoned post {}: {}", id, e);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl AsyncHandler<util::Stop> for Upkeep {
+	type Error = ();
+
+	async fn handle(
+		&mut self,
+		req: util::Stop,
+		ctx: &mut <Self as Actor>::Context,
+	) -> Result<(), Self::Error> {
+		ctx.stop();
+		req.done();
+		Ok(())
+	}
+}
@@ -1,10 +1,13 @@
 use crate::message::Message;
 use actix::prelude::*;
+use actix_web::{HttpRequest, HttpResponse};
 use common::MessageType;
 use serde::Serialize;
 use std::{
 	fmt::Debug,
+	net::IpAddr,
 	ops::{Deref, DerefMut},
+	str::FromStr,
 	sync::{Arc, Mutex},
 };
 
@@ -51,6 +54,29 @@ pub struct WakeUp;
 #[rtype(result = "()")]
 pub struct Pulse;
 
+/// Immediately process any buffered state and notify the sender on completion
+pub struct Flush(pub tokio::sync::oneshot::Sender<()>);
+
+impl Flush {
+	/// Notify the sender of completion, ignoring a dropped receiver
+	#[inline]
+	pub fn done(self) {
+		std::mem::drop(self.0.send(()));
+	}
+}
+
+/// Stop the actor, once all previously received messages are processed, and
+/// notify the sender on completion
+pub struct Stop(pub tokio::sync::oneshot::Sender<()>);
+
+impl Stop {
+	/// Notify the sender of completion, ignoring a dropped receiver
+	#[inline]
+	pub fn done(self) {
+		std::mem::drop(self.0.send(()));
+	}
+}
+
 /// Wrapper for caching an encoded message generated from T.
 ///
 /// Mutably dereferencing MessageCacher clears the cached message
@@ -274,3 +300,127 @@ where
 	});
 	receive.await.map_err(|e| e.to_string().into())
 }
+
+/// Return the IP address of the client that made the request
+pub fn client_ip(req: &HttpRequest) -> actix_web::Result<IpAddr> {
+	let ci = req.connection_info();
+	if crate::config::SERVER.reverse_proxied {
+		ci.realip_remote_addr()
+	} else {
+		ci.peer_addr()
+	}
+	.map(|s| s.parse::<std::net::SocketAddr>().ok())
+	.flatten()
+	.map(|a| a.ip())
+	.ok_or_else(|| {
+		actix_web::error::ErrorBadRequest("could not read client IP")
+	})
+}
+
+/// Respond with a body and a strong ETag computed from it or with 304, if the
+/// client's cached copy is still valid
+pub fn respond_with_etag(
+	req: &HttpRequest,
+	content_type: &str,
+	body: Vec<u8>,
+) -> HttpResponse {
+	use actix_web::http::header::{
+		self, CacheControl, CacheDirective, EntityTag, Header, IfNoneMatch,
+	};
+	use sha2::{Digest, Sha256};
+
+	// Live content changes constantly, so hash the response body
+	let tag = EntityTag::new_strong(format!("{:x}", Sha256::digest(&body)));
+	let cached = match IfNoneMatch::parse(req) {
+		Ok(IfNoneMatch::Any) => true,
+		Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(&tag)),
+		Err(_) => false,
+	};
+	if cached {
+		return HttpResponse::NotModified()
+			.insert_header(header::ETag(tag))
+			.finish();
+	}
+
+	HttpResponse::Ok()
+		.content_type(content_type)
+		.insert_header(header::ETag(tag))
+		// Always revalidate using the ETag
+		.insert_header(CacheControl(vec![CacheDirective::NoCache]))
+		.body(body)
+}
+
+/// IP address or CIDR range of IP addresses
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct IpRange {
+	addr: IpAddr,
+
+	/// Length of the network prefix in bits
+	prefix: u8,
+}
+
+impl IpRange {
+	/// Return, if the range contains an IP address
+	pub fn contains(&self, ip: &IpAddr) -> bool {
+		fn matches(a: u128, b: u128, len: u8, prefix: u8) -> bool {
+			prefix == 0 || a >> (len - prefix) == b >> (len - prefix)
+		}
+
+		match (&self.addr, ip) {
+			(IpAddr::V4(a), IpAddr::V4(b)) => matches(
+				u32::from(*a) as u128,
+				u32::from(*b) as u128,
+				32,
+				self.prefix,
+			),
+			(IpAddr::V6(a), IpAddr::V6(b)) => {
+				matches(u128::from(*a), u128::from(*b), 128, self.prefix)
+			}
+			_ => false,
+		}
+	}
+}
+
+impl FromStr for IpRange {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (addr, prefix) = match s.split_once('/') {
+			Some((addr, prefix)) => (addr, Some(prefix)),
+			None => (s, None),
+		};
+		let addr: IpAddr = addr.parse().map_err(|e| format!("{}: {}", e, s))?;
+		let max = if addr.is_ipv4() { 32 } else { 128 };
+		let prefix = match prefix {
+			Some(p) => match p.parse::<u8>() {
+				Ok(p) if p <= max => p,
+				_ => return Err(format!("invalid network prefix: {}", s)),
+			},
+			None => max,
+		};
+		Ok(Self { addr, prefix })
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn ip_range() {
+		let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+		let range = |s: &str| s.parse::<IpRange>().unwrap();
+
+		assert!(range("10.0.0.1").contains(&ip("10.0.0.1")));
+		assert!(!range("10.0.0.1").contains(&ip("10.0.0.2")));
+		assert!(range("10.0.0.1/32").contains(&ip("10.0.0.1")));
+		assert!(range("10.1.0.0/16").contains(&ip("10.1.255.3")));
+		assert!(!range("10.1.0.0/16").contains(&ip("10.2.0.3")));
+		assert!(range("0.0.0.0/0").contains(&ip("1.2.3.4")));
+		assert!(range("2001:db8::/32").contains(&ip("2001:db8:1::1")));
+		assert!(!range("2001:db8::/32").contains(&ip("10.0.0.1")));
+
+		assert!("10.0.0.1/33".parse::<IpRange>().is_err());
+		assert!("10.0.0/8".parse::<IpRange>().is_err());
+	}
+}